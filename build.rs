@@ -0,0 +1,6 @@
+// build.rs
+// `sqlx::migrate!` embeds `migrations/` at compile time, but cargo does not know about it:
+// without this hint, adding a migration file would NOT rebuild the binary.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
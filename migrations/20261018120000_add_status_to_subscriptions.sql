@@ -0,0 +1,5 @@
+-- Add status to subscriptions: double opt-in
+-- Existing subscribers predate double opt-in: they are considered confirmed.
+ALTER TABLE subscriptions ADD COLUMN status TEXT NULL;
+UPDATE subscriptions SET status = 'confirmed' WHERE status IS NULL;
+ALTER TABLE subscriptions ALTER COLUMN status SET NOT NULL;
@@ -0,0 +1,11 @@
+-- Create Audit Log Table
+-- Append-only record of sensitive actions (who did what, to what, and why)
+CREATE TABLE audit_log(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target TEXT NULL,
+    reason TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
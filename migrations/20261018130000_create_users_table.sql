@@ -0,0 +1,10 @@
+-- Create Users Table
+-- Humans operating the service (created with `zero2prod create-admin`)
+CREATE TABLE users(
+    user_id uuid NOT NULL,
+    PRIMARY KEY (user_id),
+    username TEXT NOT NULL UNIQUE,
+    -- PHC string (algorithm, parameters, salt and hash), never the password itself
+    password_hash TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
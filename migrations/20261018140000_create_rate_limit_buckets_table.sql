@@ -0,0 +1,9 @@
+-- Token buckets of the rate limiter, when shared by every replica (`rate_limit.store: postgres`)
+CREATE TABLE rate_limit_buckets(
+    -- e.g. `ip:203.0.113.7` or `email:ursula@example.com`
+    key TEXT NOT NULL PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at timestamptz NOT NULL
+);
+-- Buckets left alone long enough are full again: they are pruned by `updated_at`
+CREATE INDEX rate_limit_buckets_updated_at_idx ON rate_limit_buckets (updated_at);
@@ -0,0 +1,11 @@
+-- Create Email Domain Rules Table
+-- Operator-managed exceptions to the email domain policy (see `email_policy`):
+-- `allow` overrides the bundled disposable-domain list, `deny` blocks the domain outright.
+-- A rule also covers the subdomains of its domain.
+CREATE TABLE email_domain_rules(
+    domain TEXT NOT NULL,
+    PRIMARY KEY (domain),
+    kind TEXT NOT NULL CHECK (kind IN ('allow', 'deny')),
+    note TEXT NULL,
+    created_at timestamptz NOT NULL
+);
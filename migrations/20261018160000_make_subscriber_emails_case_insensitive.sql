@@ -0,0 +1,58 @@
+-- Make Subscriber Emails Case-Insensitive
+-- `Ursula@Gmail.com` and `ursula@gmail.com` are one subscriber, not two.
+
+-- 0. The case-sensitive `UNIQUE` goes first: canonical forms collide (`x@A.com` and `x@a.com`,
+--    ` x@a.com` and `x@a.com`) until step 2 deduplicates them.
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+
+-- 1. Canonical form (see `domain::SubscriberEmail`): trimmed, domain lowercased.
+--    (IDNA encoding of internationalized domains is left to the app: new rows only.)
+UPDATE subscriptions
+SET email = split_part(trim(email), '@', 1) || '@' || lower(split_part(trim(email), '@', 2))
+WHERE trim(email) LIKE '%_@_%' AND trim(email) NOT LIKE '%@%@%';
+
+-- 2. Deduplication report: the subscribers that collide once case is ignored.
+--    Per address, the confirmed one is kept (the oldest, if several): the others move here,
+--    for an operator to review (e.g. merge names) before dropping the table.
+CREATE TABLE subscription_email_duplicates(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    email TEXT NOT NULL,
+    name TEXT NOT NULL,
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL,
+    -- The subscriber left in `subscriptions` for this address
+    kept_id uuid NOT NULL,
+    removed_at timestamptz NOT NULL
+);
+
+WITH ranked AS (
+    SELECT *,
+        first_value(id) OVER same_address AS kept_id,
+        row_number() OVER same_address AS rank
+    FROM subscriptions
+    WINDOW same_address AS (
+        PARTITION BY lower(email)
+        ORDER BY status = 'confirmed' DESC, subscribed_at, id
+    )
+)
+INSERT INTO subscription_email_duplicates(id, email, name, status, subscribed_at, kept_id, removed_at)
+SELECT id, email, name, status, subscribed_at, kept_id, now()
+FROM ranked
+WHERE rank > 1;
+
+DELETE FROM subscriptions WHERE id IN (SELECT id FROM subscription_email_duplicates);
+
+DO $$
+DECLARE
+    removed bigint;
+BEGIN
+    SELECT count(*) INTO removed FROM subscription_email_duplicates;
+    IF removed > 0 THEN
+        RAISE NOTICE '% duplicate subscriber(s) moved to subscription_email_duplicates', removed;
+    END IF;
+END $$;
+
+-- 3. Case-insensitive uniqueness, replacing the case-sensitive `UNIQUE` (dropped in step 0).
+--    Lookups must compare `lower(email)` to use it.
+CREATE UNIQUE INDEX subscriptions_email_lower_key ON subscriptions (lower(email));
@@ -0,0 +1,19 @@
+-- Create API Keys Table
+-- Credentials of machine-to-machine callers (e.g. the CMS), limited to their scopes.
+-- The key itself is shown once, at creation: only its SHA-256 is kept.
+CREATE TABLE api_keys(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    -- The public part of the key (`z2p_<prefix>_<secret>`): finds the row, shows up in logs
+    prefix TEXT NOT NULL UNIQUE,
+    key_hash TEXT NOT NULL,
+    -- Who the key was issued to, e.g. `cms`
+    owner TEXT NOT NULL,
+    -- e.g. `{subscribers:read,metrics:read}`
+    scopes TEXT[] NOT NULL,
+    created_at timestamptz NOT NULL,
+    last_used_at timestamptz NULL,
+    expires_at timestamptz NULL,
+    -- Revoked keys are kept (rather than deleted): the audit log still points at them
+    revoked_at timestamptz NULL
+);
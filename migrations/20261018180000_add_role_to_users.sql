@@ -0,0 +1,8 @@
+-- Add Role To Users
+-- What an admin may do (see `authentication::Role`): `analyst` reads, `editor` also writes,
+-- `owner` also manages the users and the API keys.
+-- The admins created so far could do everything: they become owners.
+ALTER TABLE users ADD COLUMN role TEXT NULL;
+UPDATE users SET role = 'owner';
+ALTER TABLE users ALTER COLUMN role SET NOT NULL;
+ALTER TABLE users ADD CONSTRAINT users_role_check CHECK (role IN ('owner', 'editor', 'analyst'));
@@ -0,0 +1,34 @@
+//! src/audit.rs
+//! The `audit_log` table: append-only record of sensitive actions (who did what, to what, why).
+
+use chrono::Utc;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+/// Appends an entry to the audit log.
+///
+/// `target` names what the action was about, e.g. `subscriber:<id>` or `user:<username>`.
+/// Pass a transaction as `executor` when the entry must be written along with the change itself.
+pub async fn record_audit_event<'e>(
+    executor: impl PgExecutor<'e>,
+    actor: &str,
+    action: &str,
+    target: &str,
+    reason: Option<String>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log(id, actor, action, target, reason, occurred_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        actor,
+        action,
+        target,
+        reason,
+        Utc::now()
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
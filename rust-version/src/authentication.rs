@@ -0,0 +1,514 @@
+//! src/authentication.rs
+//! Authentication and authorization of the `/api/v1` callers.
+//!
+//! Three kinds of credentials:
+//! - the `api.token` of the configuration (`Bearer`): every scope, meant to bootstrap the API keys
+//! - API keys (`Bearer`, `api_keys` table, `/api/v1/api-keys`): only the scopes they were issued with
+//! - admins (`Basic` username and password, `users` table): the scopes of their `Role`
+//!
+//! Whatever the credential, a route only checks scopes (see `Scoped`).
+
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::LazyLock;
+
+use actix_web::http::StatusCode;
+use actix_web::http::header::{AUTHORIZATION, HeaderValue, WWW_AUTHENTICATE};
+use actix_web::{FromRequest, HttpRequest, HttpResponse, ResponseError, dev, web};
+use argon2::password_hash::SaltString;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use crate::audit::record_audit_event;
+use crate::problem::Problem;
+use crate::reload::LiveSettings;
+
+// `z2p_<prefix>_<secret>`: recognizable at a glance (e.g. by secret scanners)
+const API_KEY_MARKER: &str = "z2p_";
+
+/// What an API caller may do. Stored as `TEXT` in `api_keys.scopes`.
+#[derive(
+    serde::Serialize, serde::Deserialize, utoipa::ToSchema, Clone, Copy, Debug, PartialEq, Eq,
+)]
+pub enum Scope {
+    #[serde(rename = "subscribers:read")]
+    SubscribersRead,
+    #[serde(rename = "subscribers:write")]
+    SubscribersWrite,
+    #[serde(rename = "email_domain_rules:manage")]
+    EmailDomainRulesManage,
+    #[serde(rename = "metrics:read")]
+    MetricsRead,
+    #[serde(rename = "api_keys:manage")]
+    ApiKeysManage,
+    #[serde(rename = "users:manage")]
+    UsersManage,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 6] = [
+        Scope::SubscribersRead,
+        Scope::SubscribersWrite,
+        Scope::EmailDomainRulesManage,
+        Scope::MetricsRead,
+        Scope::ApiKeysManage,
+        Scope::UsersManage,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::SubscribersRead => "subscribers:read",
+            Scope::SubscribersWrite => "subscribers:write",
+            Scope::EmailDomainRulesManage => "email_domain_rules:manage",
+            Scope::MetricsRead => "metrics:read",
+            Scope::ApiKeysManage => "api_keys:manage",
+            Scope::UsersManage => "users:manage",
+        }
+    }
+}
+
+impl TryFrom<String> for Scope {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid scope", s))
+    }
+}
+
+/// What an admin (`users` table) may do. Stored as `TEXT` in `users.role`.
+#[derive(
+    serde::Serialize, serde::Deserialize, utoipa::ToSchema, Clone, Copy, Debug, PartialEq, Eq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    // Everything, including managing the users and the API keys
+    Owner,
+    // What an analyst does, plus changing the subscribers and the email domain rules
+    Editor,
+    // Read-only: stats and subscribers (export included)
+    Analyst,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Editor => "editor",
+            Role::Analyst => "analyst",
+        }
+    }
+
+    /// The permissions of the role: a route requires a scope, never a role.
+    pub fn scopes(&self) -> &'static [Scope] {
+        match self {
+            Role::Owner => &Scope::ALL,
+            Role::Editor => &[
+                Scope::SubscribersRead,
+                Scope::MetricsRead,
+                Scope::SubscribersWrite,
+                Scope::EmailDomainRulesManage,
+            ],
+            Role::Analyst => &[Scope::SubscribersRead, Scope::MetricsRead],
+        }
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "owner" => Ok(Self::Owner),
+            "editor" => Ok(Self::Editor),
+            "analyst" => Ok(Self::Analyst),
+            other => Err(format!("{} is not a valid role", other)),
+        }
+    }
+}
+
+/// Proof that the request carried valid credentials (bearer token or admin password).
+///
+/// Add it as a handler argument to protect a route:
+/// the handler is never invoked for unauthenticated requests (`401 Unauthorized`).
+/// To require a scope as well, use `Scoped` instead.
+#[derive(Debug, Clone)]
+pub struct ApiCaller {
+    // Recorded in the audit log as the actor, e.g. `api-token`, `api-key:3f9a0c1e` or `user:alice`
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl FromRequest for ApiCaller {
+    type Error = AuthError;
+    // An API key is looked up in the database: boxed, since `async fn` cannot name its future
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut dev::Payload) -> Self::Future {
+        // Everything the future needs is taken out of `req` now: it cannot borrow it
+        let credentials = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(Credentials::parse);
+        let settings = req
+            .app_data::<web::Data<LiveSettings>>()
+            .map(|live| live.current());
+        let db_conn = req.app_data::<web::Data<PgPool>>().cloned();
+        Box::pin(async move {
+            let (Some(credentials), Some(settings)) = (credentials, settings) else {
+                return Err(AuthError::Unauthorized);
+            };
+            let token = match credentials {
+                Credentials::Basic { username, password } => {
+                    let db_conn = db_conn.ok_or(AuthError::Unauthorized)?;
+                    return authenticate_user(username, password, &db_conn).await;
+                }
+                Credentials::Bearer(token) => token,
+            };
+            if token.expose_secret().starts_with(API_KEY_MARKER) {
+                let db_conn = db_conn.ok_or(AuthError::Unauthorized)?;
+                return authenticate_api_key(&token, &db_conn).await;
+            }
+            // The current token: it can be rotated without a restart (see `reload`)
+            if constant_time_eq(
+                token.expose_secret().as_bytes(),
+                settings.api.token.expose_secret().as_bytes(),
+            ) {
+                Ok(ApiCaller {
+                    name: "api-token".into(),
+                    scopes: Scope::ALL.to_vec(),
+                })
+            } else {
+                Err(AuthError::Unauthorized)
+            }
+        })
+    }
+}
+
+/// The scheme of an `Authorization` header, and what follows it:
+/// e.g. `("Basic", "dXNlcjpwYXNzd29yZA==")`.
+pub fn split_scheme(header: &str) -> Option<(&str, &str)> {
+    header.split_once(' ')
+}
+
+// The `Authorization` header, decoded
+enum Credentials {
+    Bearer(Secret<String>),
+    Basic {
+        username: String,
+        password: Secret<String>,
+    },
+}
+
+impl Credentials {
+    fn parse(header: &str) -> Option<Credentials> {
+        // RFC 9110: the scheme is case-insensitive (`bearer`, `BASIC`, ...)
+        let (scheme, credentials) = split_scheme(header)?;
+        if scheme.eq_ignore_ascii_case("Bearer") {
+            return Some(Credentials::Bearer(Secret::new(credentials.to_string())));
+        }
+        if !scheme.eq_ignore_ascii_case("Basic") {
+            return None;
+        }
+        // RFC 7617: base64 of `username:password`
+        let decoded = BASE64.decode(credentials).ok()?;
+        let decoded = String::from_utf8(decoded).ok()?;
+        let (username, password) = decoded.split_once(':')?;
+        Some(Credentials::Basic {
+            username: username.to_string(),
+            password: Secret::new(password.to_string()),
+        })
+    }
+}
+
+/// A scope `Scoped` can require: one marker type per `Scope` (see `scopes`).
+pub trait RequiredScope {
+    const SCOPE: Scope;
+}
+
+/// The marker types of `Scoped`, e.g. `Scoped<scopes::SubscribersRead>`.
+pub mod scopes {
+    use super::{RequiredScope, Scope};
+
+    pub struct SubscribersRead;
+    pub struct SubscribersWrite;
+    pub struct EmailDomainRulesManage;
+    pub struct MetricsRead;
+    pub struct ApiKeysManage;
+    pub struct UsersManage;
+
+    impl RequiredScope for SubscribersRead {
+        const SCOPE: Scope = Scope::SubscribersRead;
+    }
+    impl RequiredScope for SubscribersWrite {
+        const SCOPE: Scope = Scope::SubscribersWrite;
+    }
+    impl RequiredScope for EmailDomainRulesManage {
+        const SCOPE: Scope = Scope::EmailDomainRulesManage;
+    }
+    impl RequiredScope for MetricsRead {
+        const SCOPE: Scope = Scope::MetricsRead;
+    }
+    impl RequiredScope for ApiKeysManage {
+        const SCOPE: Scope = Scope::ApiKeysManage;
+    }
+    impl RequiredScope for UsersManage {
+        const SCOPE: Scope = Scope::UsersManage;
+    }
+}
+
+/// An `ApiCaller` granted the scope `S`: `403 Forbidden` otherwise.
+///
+/// The scope is part of the handler's signature, e.g. `caller: Scoped<scopes::SubscribersRead>`:
+/// a route cannot forget to check it.
+/// Scala equivalent: a phantom type parameter.
+pub struct Scoped<S> {
+    pub caller: ApiCaller,
+    scope: PhantomData<S>,
+}
+
+impl<S> Deref for Scoped<S> {
+    type Target = ApiCaller;
+    fn deref(&self) -> &ApiCaller {
+        &self.caller
+    }
+}
+
+impl<S: RequiredScope + 'static> FromRequest for Scoped<S> {
+    type Error = AuthError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        let caller = ApiCaller::from_request(req, payload);
+        let db_conn = req.app_data::<web::Data<PgPool>>().cloned();
+        // e.g. `DELETE /api/v1/subscribers/<id>`: the target of the audit log entry
+        let route = format!("{} {}", req.method(), req.path());
+        Box::pin(async move {
+            let caller = caller.await?;
+            if !caller.scopes.contains(&S::SCOPE) {
+                tracing::warn!(caller = %caller.name, scope = S::SCOPE.as_str(), "Missing scope");
+                if let Some(db_conn) = db_conn {
+                    let reason = format!("Missing the `{}` scope", S::SCOPE.as_str());
+                    let recorded = record_audit_event(
+                        db_conn.get_ref(),
+                        &caller.name,
+                        "access.denied",
+                        &route,
+                        Some(reason),
+                    )
+                    .await;
+                    // Still a 403: an unrecorded denial is no reason to let the caller through
+                    if let Err(e) = recorded {
+                        tracing::error!("Failed to record the denied access: {:?}", e);
+                    }
+                }
+                return Err(AuthError::InsufficientScope(S::SCOPE));
+            }
+            Ok(Scoped {
+                caller,
+                scope: PhantomData,
+            })
+        })
+    }
+}
+
+/// A new API key: `(prefix, key)`, the key being `z2p_<prefix>_<secret>`.
+/// Random bytes from the OS, hex-encoded: 32 bits of prefix, 192 of secret.
+pub fn generate_api_key() -> (String, Secret<String>) {
+    let mut prefix = [0u8; 4];
+    let mut secret = [0u8; 24];
+    OsRng.fill_bytes(&mut prefix);
+    OsRng.fill_bytes(&mut secret);
+    let prefix = hex::encode(prefix);
+    let key = format!("{}{}_{}", API_KEY_MARKER, prefix, hex::encode(secret));
+    (prefix, Secret::new(key))
+}
+
+/// What `api_keys.key_hash` holds.
+///
+/// SHA-256 rather than Argon2 (see `compute_password_hash`): a slow hash protects
+/// guessable passwords, 192 random bits cannot be guessed anyway.
+pub fn hash_api_key(key: &Secret<String>) -> String {
+    hex::encode(Sha256::digest(key.expose_secret().as_bytes()))
+}
+
+async fn authenticate_api_key(
+    key: &Secret<String>,
+    db_conn: &PgPool,
+) -> Result<ApiCaller, AuthError> {
+    let prefix = key
+        .expose_secret()
+        .strip_prefix(API_KEY_MARKER)
+        .and_then(|rest| rest.split_once('_'))
+        .map(|(prefix, _)| prefix)
+        .ok_or(AuthError::Unauthorized)?;
+    let row = sqlx::query!(
+        r#"
+        SELECT id, key_hash, scopes FROM api_keys
+        WHERE prefix = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > $2)
+        "#,
+        prefix,
+        Utc::now()
+    )
+    .fetch_optional(db_conn)
+    .await
+    .map_err(AuthError::Unexpected)?
+    .ok_or(AuthError::Unauthorized)?;
+    if !constant_time_eq(hash_api_key(key).as_bytes(), row.key_hash.as_bytes()) {
+        return Err(AuthError::Unauthorized);
+    }
+
+    sqlx::query!(
+        "UPDATE api_keys SET last_used_at = $2 WHERE id = $1",
+        row.id,
+        Utc::now()
+    )
+    .execute(db_conn)
+    .await
+    .map_err(AuthError::Unexpected)?;
+    Ok(ApiCaller {
+        name: format!("api-key:{}", prefix),
+        // A scope we no longer know grants nothing
+        scopes: row
+            .scopes
+            .into_iter()
+            .filter_map(|scope| Scope::try_from(scope).ok())
+            .collect(),
+    })
+}
+
+// Verified against when the user does not exist: the response takes as long as for a wrong
+// password, it does not tell which usernames exist.
+static DUMMY_PASSWORD_HASH: LazyLock<String> = LazyLock::new(|| {
+    compute_password_hash(&Secret::new("not the password of anybody".to_string()))
+        .map(|hash| hash.expose_secret().clone())
+        .expect("Failed to hash the dummy password")
+});
+
+async fn authenticate_user(
+    username: String,
+    password: Secret<String>,
+    db_conn: &PgPool,
+) -> Result<ApiCaller, AuthError> {
+    let user = sqlx::query!(
+        "SELECT password_hash, role FROM users WHERE username = $1",
+        username
+    )
+    .fetch_optional(db_conn)
+    .await
+    .map_err(AuthError::Unexpected)?;
+    let (password_hash, role) = match user {
+        Some(user) => (user.password_hash, Some(user.role)),
+        None => (DUMMY_PASSWORD_HASH.clone(), None),
+    };
+    // Argon2 is slow on purpose (CPU-bound, tens of milliseconds): off the async workers
+    let verified = web::block(move || {
+        PasswordHash::new(&password_hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.expose_secret().as_bytes(), &hash)
+                .is_ok()
+        })
+    })
+    .await
+    .unwrap_or(false);
+    let role = match role {
+        Some(role) if verified => role,
+        _ => return Err(AuthError::Unauthorized),
+    };
+    let role =
+        Role::try_from(role).map_err(|e| AuthError::Unexpected(sqlx::Error::Decode(e.into())))?;
+
+    Ok(ApiCaller {
+        name: format!("user:{}", username),
+        scopes: role.scopes().to_vec(),
+    })
+}
+
+// A naive `==` returns as soon as a byte differs:
+// the response time would tell an attacker how many leading bytes they got right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Hashes a password with Argon2id and a random salt.
+/// The result is a PHC string (`$argon2id$v=19$m=...`): it embeds the parameters and the salt,
+/// nothing else is needed to verify a password against it later.
+pub fn compute_password_hash(
+    password: &Secret<String>,
+) -> Result<Secret<String>, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default().hash_password(password.expose_secret().as_bytes(), &salt)?;
+    Ok(Secret::new(hash.to_string()))
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    Unauthorized,
+    // Authenticated, but not granted this scope
+    InsufficientScope(Scope),
+    // e.g. the database is down: not the caller's fault
+    Unexpected(sqlx::Error),
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::Unauthorized => write!(f, "Missing or invalid credentials"),
+            AuthError::InsufficientScope(scope) => {
+                write!(f, "This credential lacks the `{}` scope", scope.as_str())
+            }
+            AuthError::Unexpected(_) => write!(f, "Something went wrong on our side"),
+        }
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AuthError::InsufficientScope(_) => StatusCode::FORBIDDEN,
+            AuthError::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // RFC 6750: the `WWW-Authenticate` challenge tells the client what went wrong
+        let (code, challenge) = match self {
+            AuthError::Unauthorized => (
+                "unauthorized",
+                r#"Bearer, Basic realm="zero2prod""#.to_string(),
+            ),
+            AuthError::InsufficientScope(scope) => (
+                "insufficient_scope",
+                format!(
+                    r#"Bearer error="insufficient_scope", scope="{}""#,
+                    scope.as_str()
+                ),
+            ),
+            AuthError::Unexpected(e) => {
+                tracing::error!("Failed to authenticate the caller: {:?}", e);
+                ("internal_error", String::new())
+            }
+        };
+        let mut response = Problem::new(self.status_code())
+            .with_type(&code.replace('_', "-"))
+            .with_detail(self.to_string())
+            .with("code", code)
+            .error_response();
+        if let Ok(challenge) = HeaderValue::from_str(&challenge)
+            && !challenge.is_empty()
+        {
+            response.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+        }
+        response
+    }
+}
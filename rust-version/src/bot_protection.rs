@@ -0,0 +1,150 @@
+//! src/bot_protection.rs
+//! Bot checks of the signup form, for the spam rate limiting cannot catch (one request per IP).
+//!
+//! When `bot_protection.enabled`, a submission must come with:
+//! - an EMPTY honeypot field (`website`): hidden to humans, filled in by naive bots
+//! - a form token (`GET /subscription/form-token`), signed by us when the form was served:
+//!   submitted too quickly (a script) or too late, it gives the sender away. It is also
+//!   accepted ONCE: replayed (e.g. fetched once by a script, reused at will), it is dropped.
+//!
+//! Detected bots get the same `200 OK` as anyone else: they learn nothing from us.
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::configuration::BotProtectionSettings;
+use crate::rate_limit::RateLimiter;
+
+/// The name of the honeypot field of the signup form
+pub const HONEYPOT_FIELD: &str = "website";
+
+/// What gave a bot away, i.e. the label of the rejection metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotSignal {
+    Honeypot,
+    MissingToken,
+    InvalidToken,
+    TooFast,
+    Expired,
+    Replayed,
+}
+
+impl BotSignal {
+    pub const ALL: [BotSignal; 6] = [
+        BotSignal::Honeypot,
+        BotSignal::MissingToken,
+        BotSignal::InvalidToken,
+        BotSignal::TooFast,
+        BotSignal::Expired,
+        BotSignal::Replayed,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BotSignal::Honeypot => "honeypot",
+            BotSignal::MissingToken => "missing_token",
+            BotSignal::InvalidToken => "invalid_token",
+            BotSignal::TooFast => "too_fast",
+            BotSignal::Expired => "expired",
+            BotSignal::Replayed => "replayed",
+        }
+    }
+}
+
+/// A form token: `<issued at, in seconds since the epoch>.<nonce>.<HMAC-SHA256 of both, hex>`.
+/// The signature vouches for the timestamp; the nonce tells apart the forms served within
+/// the same second (each token is spent on its own, see `spend_form_token`).
+pub fn issue_form_token(secret: &Secret<String>, issued_at: DateTime<Utc>) -> String {
+    let message = format!("{}.{}", issued_at.timestamp(), Uuid::new_v4().simple());
+    let signature = hex::encode(mac(secret, &message).finalize().into_bytes());
+    format!("{}.{}", message, signature)
+}
+
+/// A genuine form token, neither too fresh nor expired, yet to be spent.
+#[derive(Debug)]
+pub struct FormToken {
+    // The signed part of the token: the signature itself could be re-encoded (e.g. in uppercase)
+    message: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Checks a submission, given its honeypot field and its form token.
+/// The token is returned to be spent, once the submission is accepted (none when disabled).
+pub fn check_submission(
+    settings: &BotProtectionSettings,
+    honeypot: Option<&str>,
+    form_token: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<Option<FormToken>, BotSignal> {
+    if !settings.enabled {
+        return Ok(None);
+    }
+    if honeypot.is_some_and(|value| !value.is_empty()) {
+        return Err(BotSignal::Honeypot);
+    }
+    let form_token = form_token
+        .filter(|token| !token.is_empty())
+        .ok_or(BotSignal::MissingToken)?;
+    let (message, issued_at) = verify_form_token(&settings.form_secret, form_token)?;
+
+    let age = now.timestamp() - issued_at;
+    if age < settings.min_fill_seconds as i64 {
+        return Err(BotSignal::TooFast);
+    }
+    if age > settings.max_age_seconds as i64 {
+        return Err(BotSignal::Expired);
+    }
+    let expires_at = DateTime::from_timestamp(issued_at, 0).ok_or(BotSignal::InvalidToken)?
+        + chrono::Duration::seconds(settings.max_age_seconds as i64);
+    Ok(Some(FormToken {
+        message: message.to_string(),
+        expires_at,
+    }))
+}
+
+/// Marks `form_token` as used, in the store of the rate limiter: `Replayed` if it already was.
+/// It is remembered until it expires, from then on `check_submission` refuses it anyway.
+///
+/// Spent once the submission is known to be acceptable: a human fixing a typo in their
+/// address resubmits the same form, with the same token.
+pub async fn spend_form_token(
+    limiter: &RateLimiter,
+    form_token: &FormToken,
+) -> Result<(), BotSignal> {
+    let key = format!("form_token:{}", form_token.message);
+    match limiter.spend(&key, form_token.expires_at).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(BotSignal::Replayed),
+        // Like the rate limits: better to let a replay through than to turn everyone away
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to spend the form token");
+            Ok(())
+        }
+    }
+}
+
+// The signed message of a token carrying OUR signature, and its issued-at timestamp
+fn verify_form_token<'a>(
+    secret: &Secret<String>,
+    form_token: &'a str,
+) -> Result<(&'a str, i64), BotSignal> {
+    let (message, signature) = form_token.rsplit_once('.').ok_or(BotSignal::InvalidToken)?;
+    let signature = hex::decode(signature).map_err(|_| BotSignal::InvalidToken)?;
+    // `verify_slice` compares in constant time (see `authentication::constant_time_eq`)
+    mac(secret, message)
+        .verify_slice(&signature)
+        .map_err(|_| BotSignal::InvalidToken)?;
+    let (issued_at, _nonce) = message.split_once('.').ok_or(BotSignal::InvalidToken)?;
+    let issued_at = issued_at.parse().map_err(|_| BotSignal::InvalidToken)?;
+    Ok((message, issued_at))
+}
+
+fn mac(secret: &Secret<String>, message: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(message.as_bytes());
+    mac
+}
@@ -0,0 +1,309 @@
+//! src/cli.rs
+//! Subcommands of the `zero2prod` binary: serving, but also day-to-day operations.
+//!
+//! Every command works from the same `Settings` and connection pool as the server.
+//! Commands write their result to `out` (stdout in `main.rs`), logs go to stderr:
+//! `zero2prod subscribers export > subscribers.csv` stays a clean CSV.
+
+use std::io::Write;
+
+use chrono::Utc;
+use clap::{Parser, Subcommand};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::audit::record_audit_event;
+use crate::authentication::{Role, compute_password_hash};
+use crate::configuration::Settings;
+use crate::domain::{SubscriberEmail, SubscriptionStatus};
+use crate::migrations::{pending_migrations, run_migrations};
+
+/// Actor recorded in the audit log for the changes made from the command line
+pub const CLI_ACTOR: &str = "cli";
+
+// `derive(Parser)` generates the argument parsing (and `--help`) from the struct definition
+// Scala equivalent: a scopt/decline parser, but declared through the types
+#[derive(Parser, Debug)]
+#[command(name = "zero2prod", about = "Newsletter service")]
+pub struct Cli {
+    // No subcommand at all means `serve`: the Docker ENTRYPOINT keeps working unchanged.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+// NOTE: no `issues list|retry` yet. The app neither publishes newsletter issues nor
+// queues their delivery, so there is no failed delivery to list or retry: those two
+// commands come with the delivery queue, on top of its state.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Start the HTTP server (the default)
+    Serve,
+    /// Apply the pending database migrations (embedded in the binary)
+    Migrate {
+        /// Only list the pending migrations, without applying them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Create an admin user; its generated password is printed once
+    CreateAdmin {
+        #[arg(long)]
+        username: String,
+        /// `owner`, `editor` or `analyst`
+        #[arg(long, value_parser = parse_role, default_value = "owner")]
+        role: Role,
+    },
+    /// Replace the password of a user with a newly generated one
+    ResetPassword {
+        #[arg(long)]
+        username: String,
+    },
+    /// Inspect and manage the subscribers
+    Subscribers {
+        #[command(subcommand)]
+        command: SubscribersCommand,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SubscribersCommand {
+    /// One subscriber per line (tab-separated)
+    List {
+        /// `pending_confirmation` or `confirmed`
+        #[arg(long, value_parser = parse_status)]
+        status: Option<SubscriptionStatus>,
+    },
+    /// Every subscriber, as CSV (with a header row)
+    Export,
+    /// Delete a subscriber (recorded in the audit log)
+    Delete {
+        /// Id or email of the subscriber
+        subscriber: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Load the configuration and print it, secrets redacted
+    Check,
+}
+
+fn parse_status(s: &str) -> Result<SubscriptionStatus, String> {
+    SubscriptionStatus::try_from(s.to_string())
+}
+
+fn parse_role(s: &str) -> Result<Role, String> {
+    Role::try_from(s.to_string())
+}
+
+// Boxed: the commands only report errors to a human, nobody matches on them.
+pub type CliError = Box<dyn std::error::Error + Send + Sync>;
+
+pub fn config_check(config: &Settings, out: &mut impl Write) -> Result<(), CliError> {
+    // `{:#?}`: pretty-printed Debug, `Secret<T>` prints as `Secret([REDACTED ...])`
+    writeln!(out, "{:#?}", config)?;
+    Ok(())
+}
+
+pub async fn migrate(
+    db_conn_pool: &PgPool,
+    dry_run: bool,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    if !dry_run {
+        run_migrations(db_conn_pool).await?;
+        writeln!(out, "Database is up to date.")?;
+        return Ok(());
+    }
+    let pending = pending_migrations(db_conn_pool).await?;
+    if pending.is_empty() {
+        writeln!(out, "No pending migration.")?;
+    }
+    for migration in pending {
+        writeln!(
+            out,
+            "pending: {} {}",
+            migration.version, migration.description
+        )?;
+    }
+    Ok(())
+}
+
+pub async fn create_admin(
+    db_conn_pool: &PgPool,
+    username: &str,
+    role: Role,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    let password = generate_password();
+    let password_hash = compute_password_hash(&password)?;
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, created_at, role)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        username,
+        password_hash.expose_secret(),
+        Utc::now(),
+        role.as_str()
+    )
+    .execute(db_conn_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+            format!("User `{}` already exists", username).into()
+        }
+        e => CliError::from(e),
+    })?;
+
+    writeln!(out, "Created admin `{}` ({})", username, role.as_str())?;
+    writeln!(
+        out,
+        "Password (shown only once): {}",
+        password.expose_secret()
+    )?;
+    Ok(())
+}
+
+pub async fn reset_password(
+    db_conn_pool: &PgPool,
+    username: &str,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    let password = generate_password();
+    let password_hash = compute_password_hash(&password)?;
+    let updated = sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE username = $2",
+        password_hash.expose_secret(),
+        username
+    )
+    .execute(db_conn_pool)
+    .await?
+    .rows_affected();
+    if updated == 0 {
+        return Err(format!("No user named `{}`", username).into());
+    }
+
+    writeln!(
+        out,
+        "New password for `{}` (shown only once): {}",
+        username,
+        password.expose_secret()
+    )?;
+    Ok(())
+}
+
+// 122 random bits (UUID v4 are generated from the OS CSPRNG), printable as 32 hex characters
+fn generate_password() -> Secret<String> {
+    Secret::new(Uuid::new_v4().simple().to_string())
+}
+
+pub async fn list_subscribers(
+    db_conn_pool: &PgPool,
+    status: Option<SubscriptionStatus>,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR status = $1)
+        ORDER BY subscribed_at, id
+        "#,
+        status.map(|s| s.as_str())
+    )
+    .fetch_all(db_conn_pool)
+    .await?;
+
+    for row in rows {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}",
+            row.id,
+            row.email,
+            row.name,
+            row.status,
+            row.subscribed_at.to_rfc3339()
+        )?;
+    }
+    Ok(())
+}
+
+pub async fn export_subscribers(
+    db_conn_pool: &PgPool,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    let rows = sqlx::query!(
+        "SELECT id, email, name, status, subscribed_at FROM subscriptions ORDER BY subscribed_at, id"
+    )
+    .fetch_all(db_conn_pool)
+    .await?;
+
+    writeln!(out, "id,email,name,status,subscribed_at")?;
+    for row in rows {
+        writeln!(
+            out,
+            "{},{},{},{},{}",
+            row.id,
+            csv_field(&row.email),
+            csv_field(&row.name),
+            row.status,
+            row.subscribed_at.to_rfc3339()
+        )?;
+    }
+    Ok(())
+}
+
+// RFC 4180: a field containing a comma, a quote or a line break is quoted,
+// its quotes are doubled.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub async fn delete_subscriber(
+    db_conn_pool: &PgPool,
+    subscriber: &str,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    // Either an id or an email: an email is never a valid UUID
+    let id = Uuid::parse_str(subscriber).ok();
+    // As stored, if it is a valid address
+    let email = SubscriberEmail::parse(subscriber)
+        .map(|email| email.to_string())
+        .unwrap_or_else(|_| subscriber.to_string());
+    let mut transaction = db_conn_pool.begin().await?;
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM subscriptions
+        WHERE id = $1 OR ($1 IS NULL AND lower(email) = lower($2))
+        RETURNING id
+        "#,
+        id,
+        email
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or_else(|| format!("No subscriber matches `{}`", subscriber))?;
+    record_audit_event(
+        &mut *transaction,
+        CLI_ACTOR,
+        "subscriber.deleted",
+        &format!("subscriber:{}", deleted.id),
+        None,
+    )
+    .await?;
+    transaction.commit().await?;
+
+    writeln!(out, "Deleted subscriber {}", deleted.id)?;
+    Ok(())
+}
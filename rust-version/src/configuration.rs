@@ -1,40 +1,291 @@
 //! src/configuration.rs
 // use config::Environment;
 use secrecy::{ExposeSecret, Secret};
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+
+use crate::secrets::{default_providers, resolve_secrets};
+use crate::telemetry::LogFormat;
 /*
 * To manage configuration with config we must
 * represent our application settings as a Rust type
 * that implements serde’s Deserialize trait.
 * */
-#[derive(serde::Deserialize)]
+// Debug: printed by `zero2prod config check` (`Secret` fields are redacted)
+#[derive(serde::Deserialize, Debug)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub server: ServerSettings,
+    pub telemetry: TelemetrySettings,
+    #[serde(default)]
+    pub api: ApiSettings,
+    #[serde(default)]
+    pub rate_limit: RateLimitSettings,
+    #[serde(default)]
+    pub bot_protection: BotProtectionSettings,
+    #[serde(default)]
+    pub email_policy: EmailPolicySettings,
+    #[serde(default)]
+    pub cors: CorsSettings,
+}
+
+/// Cross-origin requests from browsers, e.g. our SPA (see `cors`), reloadable.
+/// No `allowed_origins` (the default): no CORS headers at all, same-origin requests only.
+#[derive(serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct CorsSettings {
+    // `scheme://host[:port]`, exactly as browsers send it in `Origin` (e.g. `https://app.example.com`)
+    // or `*` for any origin
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    // Request headers the browser may send along, compared case-insensitively
+    pub allowed_headers: Vec<String>,
+    // Cookies and HTTP authentication sent by the browser: not allowed with `*`
+    pub allow_credentials: bool,
+    // How long a browser may reuse the answer to a preflight request
+    pub max_age_seconds: u32,
+}
+
+impl Default for CorsSettings {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![],
+            allowed_methods: ["GET", "POST", "PUT", "PATCH", "DELETE"]
+                .map(String::from)
+                .to_vec(),
+            allowed_headers: ["Authorization", "Content-Type"].map(String::from).to_vec(),
+            allow_credentials: false,
+            max_age_seconds: 600,
+        }
+    }
+}
+
+/// Which addresses may subscribe (see `email_policy`), reloadable.
+/// The per-domain exceptions live in the database, not here.
+#[derive(serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct EmailPolicySettings {
+    // Refuse the domains of the bundled list of disposable email providers
+    pub block_disposable: bool,
+    // Local parts refused whatever the domain, compared case-insensitively
+    pub role_addresses: Vec<String>,
+}
+
+impl Default for EmailPolicySettings {
+    fn default() -> Self {
+        Self {
+            block_disposable: true,
+            role_addresses: [
+                "postmaster",
+                "abuse",
+                "hostmaster",
+                "webmaster",
+                "mailer-daemon",
+                "noreply",
+                "no-reply",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+/// Bot checks of the signup form (see `bot_protection`), reloadable.
+#[derive(serde::Deserialize, Clone, Debug)]
+// Any missing key takes its default value, e.g. `bot_protection: { enabled: true, ... }`
+#[serde(default)]
+pub struct BotProtectionSettings {
+    // Off by default: the form must first send the honeypot field and the form token
+    pub enabled: bool,
+    // Signs the form tokens (HMAC-SHA256): changing it invalidates the forms being filled
+    pub form_secret: Secret<String>,
+    // Humans take a little while to fill the form in, scripts do not
+    pub min_fill_seconds: u64,
+    // Past that, a token is refused. Before that, it is accepted ONCE: each token spent is
+    // remembered until it expires, in the `rate_limit.store`. The longer the max age, the
+    // slower the humans we wait for, but the more tokens the store holds (one per signup).
+    pub max_age_seconds: u64,
+}
+
+impl Default for BotProtectionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            form_secret: Secret::new(String::new()),
+            min_fill_seconds: 3,
+            // A tab left open all day long is still a human
+            max_age_seconds: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Limits of `POST /subscription` (see `rate_limit`): the limits can change without a restart,
+/// the store cannot.
+#[derive(serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RateLimitSettings {
+    pub store: RateLimitStore,
+    // Keyed by client IP (see `server.trusted_proxies`)
+    pub per_ip: BucketSettings,
+    // Keyed by the email address to subscribe: we do not want to spam anyone
+    pub per_email: BucketSettings,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            store: RateLimitStore::Memory,
+            per_ip: BucketSettings {
+                burst: 10,
+                per_hour: 60,
+            },
+            per_email: BucketSettings {
+                burst: 3,
+                per_hour: 5,
+            },
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitStore {
+    // Per process: each replica enforces the limits on its own
+    Memory,
+    // `rate_limit_buckets` table: the limits hold across every replica
+    Postgres,
+}
+
+/// A token bucket: up to `burst` requests at once, then `per_hour` (evenly spread).
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BucketSettings {
+    pub burst: u32,
+    pub per_hour: u32,
 }
 
-#[derive(serde::Deserialize, Clone)]
+/// The development token of `local.yaml` and `test.yaml`: refused anywhere else.
+pub const DEV_API_TOKEN: &str = "local-api-token";
+
+#[derive(serde::Deserialize, Clone, Debug)]
+// No token in `base.yaml`: an environment that does not set one fails validation
+#[serde(default)]
+pub struct ApiSettings {
+    // Bearer token granting access to the `/api/v1` endpoints (every scope)
+    pub token: Secret<String>,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        Self {
+            token: Secret::new(String::new()),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct TelemetrySettings {
+    // NOTE: never missing after `get_configuration`,
+    // a per-environment default is registered before the YAML sources are layered on top.
+    pub format: LogFormat,
+    // `EnvFilter` directives, e.g. `info` or `zero2prod=debug,sqlx=warn` (reloadable)
+    pub level: String,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
 pub struct ServerSettings {
-    pub host: Ipv4Addr,
+    // Where to listen: an IP (`127.0.0.1`, `::1`, `::`), a hostname (`localhost`)
+    // or a list of them, e.g. `[127.0.0.1, "::1"]` (all of them on `port`)
+    // NOTE: on Linux, `::` alone already accepts IPv4 connections (dual-stack)
+    // `unix:/path/to.sock` listens on a Unix domain socket instead (see `listener`)
+    #[serde(deserialize_with = "one_or_many")]
+    pub host: Vec<String>,
     pub port: u16,
+    // Reverse proxies allowed to tell us who the client is (`X-Forwarded-For`)
+    // Empty by default: we then log the TCP peer address.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+    // HTTPS on `port`, rather than plain HTTP (see `tls`)
+    // NOTE: Unix sockets stay plain HTTP, the proxy on the other end terminates TLS
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TlsSettings {
+    // PEM files, reloaded on change (their paths are only read at startup)
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    // Plain HTTP listener (same hosts) redirecting every request to HTTPS
+    #[serde(default)]
+    pub redirect_port: Option<u16>,
 }
 
 impl ServerSettings {
     // TERMINOLOGY CLARIFICATION:
-    // - TCP_SOCKET_ADDRESS: A string representing where to bind ("127.0.0.1:8000")
+    // - TCP_SOCKET_ADDRESS: where to bind (`127.0.0.1:8000`, `[::1]:8000`)
     // - TCP Socket: The actual OS resource created when .bind() is called
     // - TCP Connection: An accepted connection on that socket
-    pub fn tcp_socket_address(self) -> String {
-        format!("{}:{}", self.host, self.port)
+    /// Every address to bind, hostnames being resolved (`localhost` may give both
+    /// `127.0.0.1` and `::1`).
+    pub fn tcp_socket_addresses(&self) -> std::io::Result<Vec<SocketAddr>> {
+        self.tcp_socket_addresses_on(self.port)
     }
 
-    pub fn with_random_port(self) -> String {
+    /// Same as `tcp_socket_addresses`, on random ports (each bind gets its own)
+    pub fn with_random_port(&self) -> std::io::Result<Vec<SocketAddr>> {
         let random_port = 0; // (i.e OS scan and takes whatever is available)
-        format!("{}:{}", self.host, random_port)
+        self.tcp_socket_addresses_on(random_port)
+    }
+
+    /// Every host on another `port` (e.g. the HTTP -> HTTPS redirect, see `tls`)
+    pub fn tcp_socket_addresses_on(&self, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+        let mut addresses = vec![];
+        for host in self
+            .host
+            .iter()
+            .filter(|host| !host.starts_with(UNIX_PREFIX))
+        {
+            // `(&str, u16)`: parsed as an IP first, resolved through DNS otherwise
+            for address in (host.as_str(), port).to_socket_addrs()? {
+                if !addresses.contains(&address) {
+                    addresses.push(address);
+                }
+            }
+        }
+        Ok(addresses)
+    }
+
+    /// The `unix:` hosts, i.e. the paths of the Unix sockets to bind
+    pub fn unix_socket_paths(&self) -> Vec<PathBuf> {
+        self.host
+            .iter()
+            .filter_map(|host| host.strip_prefix(UNIX_PREFIX))
+            .map(PathBuf::from)
+            .collect()
     }
 }
 
-#[derive(serde::Deserialize, Clone)]
+const UNIX_PREFIX: &str = "unix:";
+
+// `host: 127.0.0.1` (e.g. `APP_SERVER__HOST`) or `host: [127.0.0.1, "::1"]`
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(
+        match <OneOrMany as serde::Deserialize>::deserialize(deserializer)? {
+            OneOrMany::One(host) => vec![host],
+            OneOrMany::Many(hosts) => hosts,
+        },
+    )
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
 pub struct DBUser {
     pub name: String,
     pub password: Secret<String>,
@@ -43,12 +294,16 @@ pub struct DBUser {
 // DatabaseSettings must also dervice Deserialize
 // It makes sense: all fields in a type have to be deserialisable in order for the type as a whole to be deserialisable.
 // without it, Settings is not Deserializable anymore.
-#[derive(serde::Deserialize, Clone)]
+#[derive(serde::Deserialize, Clone, Debug)]
 pub struct DatabaseSettings {
     pub name: String,
     pub host: String,
     pub port: u16,
     pub user: DBUser,
+    // Opt-in: apply the pending migrations before serving (see `migrations::run_migrations`)
+    // Off by default, a deployment pipeline usually runs `zero2prod migrate` instead.
+    #[serde(default)]
+    pub migrate_on_startup: bool,
 }
 
 impl DatabaseSettings {
@@ -65,23 +320,377 @@ impl DatabaseSettings {
     }
 }
 
-pub fn get_configuration() -> Result<Settings, config::ConfigError> {
-    let base_path = std::env::current_dir().expect("Failed to determine current dir.");
-    let config_dir = base_path.join("configuration");
+/// The directory holding the YAML files (also watched for changes, see `reload`).
+///
+/// `APP_CONFIG_DIR` when set. Otherwise the first `configuration/` directory found
+/// in the current directory or one of its parents (e.g. `cargo run` from `rust-version/`),
+/// then next to the binary.
+pub fn config_dir() -> Result<PathBuf, ConfigurationError> {
+    if let Ok(dir) = std::env::var("APP_CONFIG_DIR") {
+        return find_config_dir(vec![PathBuf::from(dir)]);
+    }
+    let current_dir = std::env::current_dir().expect("Failed to determine current dir.");
+    let mut candidates: Vec<PathBuf> = current_dir
+        .ancestors()
+        .map(|dir| dir.join("configuration"))
+        .collect();
+    if let Some(binary_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        candidates.push(binary_dir.join("configuration"));
+    }
+    find_config_dir(candidates)
+}
+
+/// The first of the `candidates` holding a `base.yaml`.
+pub fn find_config_dir(candidates: Vec<PathBuf>) -> Result<PathBuf, ConfigurationError> {
+    match candidates
+        .iter()
+        .find(|dir| dir.join("base.yaml").is_file())
+    {
+        Some(dir) => Ok(dir.clone()),
+        None => Err(ConfigurationError::NotFound {
+            searched: candidates
+                .into_iter()
+                .map(|dir| dir.join("base.yaml"))
+                .collect(),
+        }),
+    }
+}
+
+pub fn get_configuration() -> Result<Settings, ConfigurationError> {
+    let config_dir = config_dir()?;
     let env: Environment = std::env::var("APP_ENVIRONMENT")
         .unwrap_or_else(|_| "local".into())
         .try_into()
-        .expect("Failed to parse APP_ENVIRONMENT");
+        .map_err(|e| ConfigurationError::invalid("APP_ENVIRONMENT", e))?;
+    load_configuration(&config_dir, &env, std::env::vars())
+}
+
+/// `get_configuration`, with every input made explicit (handy in tests).
+///
+/// Sources, from the lowest to the highest priority:
+/// per-environment defaults, `base.yaml`, `<environment>.yaml`, then the `APP_*` variables.
+/// Secrets given by reference (`password_file`, `token_env`, ...) are then resolved.
+pub fn load_configuration(
+    config_dir: &Path,
+    env: &Environment,
+    env_vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<Settings, ConfigurationError> {
+    let env_vars: Vec<(String, String)> = env_vars.into_iter().collect();
     let env_config_file = format!("{}.yaml", env.as_str());
-    let settings = config::Config::builder()
+    // Checked upfront: `config` would only report the first missing file, without the full path
+    let missing: Vec<PathBuf> = ["base.yaml", env_config_file.as_str()]
+        .iter()
+        .map(|file| config_dir.join(file))
+        .filter(|path| !path.is_file())
+        .collect();
+    if !missing.is_empty() {
+        return Err(ConfigurationError::NotFound { searched: missing });
+    }
+    let mut config = config::Config::builder()
+        // Defaults have the lowest priority: any YAML file can still override them.
+        .set_default("telemetry.format", env.default_log_format().as_str())?
+        .set_default("telemetry.level", "info")?
         .add_source(config::File::from(config_dir.join("base.yaml")))
         .add_source(config::File::from(config_dir.join(env_config_file)))
+        // Add in settings from environment variables (with a prefix of APP and '__' as separator)
+        // E.g. `APP_API__TOKEN=...` would set `Settings.api.token`
+        // Handy for secrets that should not be baked into the image.
+        .add_source(
+            config::Environment::with_prefix("APP")
+                .prefix_separator("_")
+                .separator("__")
+                .source(Some(setting_variables(env_vars.clone()))),
+        )
         .build()?;
-    settings.try_deserialize::<Settings>()
+
+    // STRICT DESERIALIZATION
+    // `#[serde(deny_unknown_fields)]` would give up at the first unknown key:
+    // serde_ignored reports every one of them instead, then we reject the whole configuration.
+    // serde_path_to_error tells which key a type error (e.g. `port: eighty`) is about.
+    let secret_problems = resolve_secrets(&mut config.cache, &default_providers(env_vars));
+    let mut problems = vec![];
+    let mut unknown_keys = vec![];
+    let mut on_unknown_key = |path: serde_ignored::Path| unknown_keys.push(path.to_string());
+    let deserializer = serde_ignored::Deserializer::new(config.clone(), &mut on_unknown_key);
+    let settings: Result<Settings, _> = serde_path_to_error::deserialize(deserializer);
+    for key in unknown_keys {
+        problems.push(ConfigProblem::new(&config, &key, "unknown key"));
+    }
+    match settings {
+        Ok(settings) => {
+            problems.extend(
+                settings
+                    .validate(env)
+                    .into_iter()
+                    .map(|(key, message)| ConfigProblem::new(&config, key, message)),
+            );
+            if problems.is_empty() && secret_problems.is_empty() {
+                return Ok(settings);
+            }
+        }
+        Err(e) => {
+            let key = e.path().to_string();
+            problems.push(ConfigProblem::new(
+                &config,
+                &key,
+                e.into_inner().to_string(),
+            ));
+        }
+    }
+    // A secret which could not be resolved is already reported through its reference:
+    // e.g. `api.token_file: cannot read ...`, not `api.token: must not be empty` on top of it.
+    problems.retain(|p| {
+        !secret_problems
+            .iter()
+            .any(|s| s.key.starts_with(&format!("{}_", p.key)))
+    });
+    Err(ConfigurationError::Invalid(
+        secret_problems.into_iter().chain(problems).collect(),
+    ))
+}
+
+// Only `APP_<SECTION>__<KEY>` variables are settings:
+// `APP_ENVIRONMENT` picks the YAML file, it must not show up as an (unknown) `environment` key.
+fn setting_variables(
+    env_vars: impl IntoIterator<Item = (String, String)>,
+) -> config::Map<String, String> {
+    env_vars
+        .into_iter()
+        .filter(|(name, _)| name.starts_with("APP_") && name.contains("__"))
+        .collect()
+}
+
+impl Settings {
+    /// Semantic checks, beyond what the types already enforce.
+    /// Returns every `(key, problem)` found, not just the first one.
+    fn validate(&self, env: &Environment) -> Vec<(&'static str, String)> {
+        let mut problems = vec![];
+        // The port is of no use to the Unix sockets
+        let listens_on_tcp = self
+            .server
+            .host
+            .iter()
+            .any(|host| !host.starts_with(UNIX_PREFIX));
+        if self.server.port == 0 && listens_on_tcp {
+            problems.push(("server.port", "must not be 0".to_string()));
+        }
+        if self.server.host.is_empty() {
+            problems.push(("server.host", "must list at least one address".to_string()));
+        }
+        // Hostnames are only resolved when binding: no DNS lookup here
+        if self.server.host.iter().any(|host| host.trim().is_empty()) {
+            problems.push(("server.host", "must not be empty".to_string()));
+        }
+        if self
+            .server
+            .unix_socket_paths()
+            .iter()
+            .any(|path| path.as_os_str().is_empty())
+        {
+            problems.push((
+                "server.host",
+                "`unix:` must be followed by a path".to_string(),
+            ));
+        }
+        if cfg!(not(unix)) && !self.server.unix_socket_paths().is_empty() {
+            problems.push((
+                "server.host",
+                "`unix:` hosts need Unix domain sockets, which this platform does not have"
+                    .to_string(),
+            ));
+        }
+        if let Some(tls) = &self.server.tls {
+            if tls.cert_path.as_os_str().is_empty() {
+                problems.push(("server.tls.cert_path", "must not be empty".to_string()));
+            }
+            if tls.key_path.as_os_str().is_empty() {
+                problems.push(("server.tls.key_path", "must not be empty".to_string()));
+            }
+            if tls.redirect_port == Some(self.server.port) {
+                problems.push((
+                    "server.tls.redirect_port",
+                    "must differ from server.port".to_string(),
+                ));
+            }
+        }
+        if self.database.port == 0 {
+            problems.push(("database.port", "must not be 0".to_string()));
+        }
+        if self.database.host.trim().is_empty() {
+            problems.push(("database.host", "must not be empty".to_string()));
+        }
+        if self.database.name.trim().is_empty() {
+            problems.push(("database.name", "must not be empty".to_string()));
+        }
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.telemetry.level) {
+            problems.push(("telemetry.level", e.to_string()));
+        }
+        for (key, bucket) in [
+            ("rate_limit.per_ip", &self.rate_limit.per_ip),
+            ("rate_limit.per_email", &self.rate_limit.per_email),
+        ] {
+            // A zero would block every request, for good
+            if bucket.burst == 0 || bucket.per_hour == 0 {
+                problems.push((key, "burst and per_hour must not be 0".to_string()));
+            }
+        }
+        if self.bot_protection.enabled {
+            if self.bot_protection.form_secret.expose_secret().is_empty() {
+                problems.push((
+                    "bot_protection.form_secret",
+                    "must not be empty when bot_protection is enabled".to_string(),
+                ));
+            }
+            if self.bot_protection.min_fill_seconds >= self.bot_protection.max_age_seconds {
+                problems.push((
+                    "bot_protection.max_age_seconds",
+                    "must be greater than min_fill_seconds".to_string(),
+                ));
+            }
+        }
+        for origin in &self.cors.allowed_origins {
+            let (scheme, authority) = origin.split_once("://").unwrap_or_default();
+            let valid = origin == "*"
+                || (["http", "https"].contains(&scheme)
+                    && !authority.is_empty()
+                    && !authority.contains('/'));
+            if !valid {
+                problems.push((
+                    "cors.allowed_origins",
+                    format!("{} is neither `*` nor `scheme://host[:port]`", origin),
+                ));
+            }
+        }
+        if self.cors.allow_credentials && self.cors.allowed_origins.iter().any(|o| o == "*") {
+            problems.push((
+                "cors.allow_credentials",
+                "must be false when any origin (`*`) is allowed".to_string(),
+            ));
+        }
+        for method in &self.cors.allowed_methods {
+            if actix_web::http::Method::from_bytes(method.as_bytes()).is_err() {
+                problems.push((
+                    "cors.allowed_methods",
+                    format!("{} is not an HTTP method", method),
+                ));
+            }
+        }
+        let api_token = self.api.token.expose_secret();
+        if api_token.trim().is_empty() {
+            problems.push(("api.token", "must not be empty".to_string()));
+        }
+        // It grants every scope and it is in the repository: anybody could use it
+        if api_token == DEV_API_TOKEN && !env.is_development() {
+            problems.push((
+                "api.token",
+                "must not be the development token outside local and test".to_string(),
+            ));
+        }
+        problems
+    }
+}
+
+/// Why the configuration could not be loaded.
+#[derive(Debug)]
+pub enum ConfigurationError {
+    // A source could not be read at all (e.g. invalid YAML)
+    Load(config::ConfigError),
+    // The configuration files were looked for at these paths, in vain
+    NotFound { searched: Vec<PathBuf> },
+    // Every problem found in the settings themselves
+    Invalid(Vec<ConfigProblem>),
+}
+
+/// A setting rejected during validation, and where its value came from.
+#[derive(Debug)]
+pub struct ConfigProblem {
+    // Dotted path, e.g. `server.port`
+    pub key: String,
+    // The file or the environment variable which supplied the value (if any did)
+    pub source: Option<String>,
+    pub message: String,
+}
+
+impl ConfigurationError {
+    fn invalid(key: &str, message: impl Into<String>) -> Self {
+        Self::Invalid(vec![ConfigProblem {
+            key: key.to_string(),
+            // The key is the environment variable itself
+            source: None,
+            message: message.into(),
+        }])
+    }
 }
 
+impl ConfigProblem {
+    fn new(config: &config::Config, key: &str, message: impl Into<String>) -> Self {
+        Self {
+            key: key.to_string(),
+            source: origin(config, key),
+            message: message.into(),
+        }
+    }
+}
+
+// `config` remembers where each value comes from: the file path, or "the environment"
+// (then we rebuild the variable name, e.g. `server.port` => `APP_SERVER__PORT`)
+fn origin(config: &config::Config, key: &str) -> Option<String> {
+    // `cache`: the merged tree of every source
+    let mut value = config.cache.clone();
+    for segment in key.split('.') {
+        value = value.into_table().ok()?.remove(segment)?;
+    }
+    match value.origin()? {
+        "the environment" => Some(format!("APP_{}", key.to_uppercase().replace('.', "__"))),
+        file => Some(file.to_string()),
+    }
+}
+
+impl From<config::ConfigError> for ConfigurationError {
+    fn from(e: config::ConfigError) -> Self {
+        Self::Load(e)
+    }
+}
+
+impl std::fmt::Display for ConfigurationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigurationError::Load(e) => write!(f, "Failed to load the configuration: {}", e),
+            ConfigurationError::NotFound { searched } => {
+                write!(f, "Configuration file(s) not found, searched:")?;
+                for path in searched {
+                    write!(f, "\n  - {}", path.display())?;
+                }
+                write!(
+                    f,
+                    "\nSet APP_CONFIG_DIR to the directory holding the YAML files."
+                )
+            }
+            ConfigurationError::Invalid(problems) => {
+                write!(f, "Invalid configuration ({} problem(s)):", problems.len())?;
+                for problem in problems {
+                    write!(f, "\n  - {}: {}", problem.key, problem.message)?;
+                    if let Some(source) = &problem.source {
+                        write!(f, " (from {})", source)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigurationError {}
+
 pub enum Environment {
     Local,
+    // Automated tests (`tests/api`), on the developer machine or in CI
+    Test,
+    // Production-like, for the final checks before a release
+    Staging,
     Production,
 }
 
@@ -89,9 +698,26 @@ impl Environment {
     pub fn as_str(&self) -> &'static str {
         match self {
             Environment::Local => "local",
+            Environment::Test => "test",
+            Environment::Staging => "staging",
             Environment::Production => "production",
         }
     }
+
+    /// The environments of the developer machine and CI, never exposed to anybody else.
+    pub fn is_development(&self) -> bool {
+        matches!(self, Environment::Local | Environment::Test)
+    }
+
+    /// Humans read the logs locally, machines (log aggregators) read them in production.
+    pub fn default_log_format(&self) -> LogFormat {
+        match self {
+            Environment::Local => LogFormat::Pretty,
+            // `TEST_LOG=true cargo test ... | bunyan`
+            Environment::Test => LogFormat::Bunyan,
+            Environment::Staging | Environment::Production => LogFormat::Json,
+        }
+    }
 }
 
 impl TryFrom<String> for Environment {
@@ -99,9 +725,11 @@ impl TryFrom<String> for Environment {
     fn try_from(s: String) -> Result<Self, Self::Error> {
         match s.to_lowercase().as_str() {
             "local" => Ok(Self::Local),
+            "test" => Ok(Self::Test),
+            "staging" => Ok(Self::Staging),
             "production" => Ok(Self::Production),
             other => Err(format!(
-                "{} is not a supported env.\nUse either `local` or `production`",
+                "{} is not a supported env. Use one of `local`, `test`, `staging` or `production`",
                 other
             )),
         }
@@ -0,0 +1,149 @@
+//! src/cors.rs
+//! CORS (Cross-Origin Resource Sharing): lets the browser of a page served from another origin
+//! (e.g. our SPA) call `/subscription` and `/api/v1`, as configured in `cors` (reloadable).
+//!
+//! Two kinds of cross-origin requests:
+//! - preflight: `OPTIONS` + `Access-Control-Request-Method`, the browser asking for permission.
+//!   Answered here, the request never reaches a handler (nor the API token check).
+//! - actual: any other request with an `Origin`, answered as usual, plus the headers telling
+//!   the browser the page may read the response.
+//!
+//! A request from an origin we do not know gets no CORS header at all: the browser then
+//! keeps the response from the page. (Non-browser clients do not care about CORS.)
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{
+    ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE,
+    ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, HeaderMap, HeaderValue, ORIGIN,
+    VARY,
+};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::web;
+use actix_web::{HttpResponse, ResponseError};
+
+use crate::configuration::CorsSettings;
+use crate::problem::Problem;
+use crate::reload::LiveSettings;
+
+// Response headers a cross-origin page may read, besides the CORS-safelisted ones
+const EXPOSED_HEADERS: &str = "Location, Retry-After";
+
+/// Middleware (`middleware::from_fn`) handling CORS for the resources it wraps.
+pub async fn cors(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let settings = req
+        .app_data::<web::Data<LiveSettings>>()
+        .map(|live| live.current());
+    let origin = req.headers().get(ORIGIN).cloned();
+    let (Some(settings), Some(origin)) = (settings, origin) else {
+        return next.call(req).await.map(|res| res.map_into_left_body());
+    };
+    let cors = &settings.cors;
+    if cors.allowed_origins.is_empty() {
+        return next.call(req).await.map(|res| res.map_into_left_body());
+    }
+    let allowed = is_allowed_origin(cors, &origin);
+
+    if req.method() == Method::OPTIONS && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+    {
+        let preflight = match allowed {
+            true => preflight(cors, req.headers()),
+            false => Err("origin"),
+        };
+        let response = match preflight {
+            Ok(mut response) => {
+                allow_origin(cors, &origin, response.headers_mut());
+                response
+            }
+            // Told explicitly rather than through a missing header: easier to debug
+            Err(what) => Problem::new(StatusCode::FORBIDDEN)
+                .with_type("cors-rejected")
+                .with_title("Cross-origin request not allowed")
+                .with_detail(format!("This {} is not allowed (see `cors`)", what))
+                .error_response(),
+        };
+        let mut response = req.into_response(response);
+        response.headers_mut().insert(
+            VARY,
+            HeaderValue::from_static(
+                "Origin, Access-Control-Request-Method, Access-Control-Request-Headers",
+            ),
+        );
+        return Ok(response.map_into_right_body());
+    }
+
+    let mut response = next.call(req).await?;
+    let headers = response.headers_mut();
+    // The answer depends on `Origin`: caches must not serve it to another one
+    headers.append(VARY, HeaderValue::from_static("Origin"));
+    if allowed {
+        allow_origin(cors, &origin, headers);
+        headers.insert(
+            ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static(EXPOSED_HEADERS),
+        );
+    }
+    Ok(response.map_into_left_body())
+}
+
+fn is_allowed_origin(cors: &CorsSettings, origin: &HeaderValue) -> bool {
+    cors.allowed_origins
+        .iter()
+        .any(|allowed| allowed == "*" || allowed.as_bytes() == origin.as_bytes())
+}
+
+// The answer to an acceptable preflight request, or what is not acceptable about it
+fn preflight(cors: &CorsSettings, request: &HeaderMap) -> Result<HttpResponse, &'static str> {
+    let method = request
+        .get(ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !cors.allowed_methods.iter().any(|allowed| allowed == method) {
+        return Err("method");
+    }
+    // e.g. `content-type, authorization`
+    let requested_headers = request
+        .get(ACCESS_CONTROL_REQUEST_HEADERS)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let all_allowed = requested_headers
+        .split(',')
+        .map(str::trim)
+        .filter(|header| !header.is_empty())
+        .all(|header| {
+            cors.allowed_headers
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(header))
+        });
+    if !all_allowed {
+        return Err("request header");
+    }
+
+    Ok(HttpResponse::NoContent()
+        .insert_header((
+            ACCESS_CONTROL_ALLOW_METHODS,
+            cors.allowed_methods.join(", "),
+        ))
+        .insert_header((
+            ACCESS_CONTROL_ALLOW_HEADERS,
+            cors.allowed_headers.join(", "),
+        ))
+        .insert_header((ACCESS_CONTROL_MAX_AGE, cors.max_age_seconds.to_string()))
+        .finish())
+}
+
+fn allow_origin(cors: &CorsSettings, origin: &HeaderValue, headers: &mut HeaderMap) {
+    // The origin itself rather than `*`: the only option with credentials anyway
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+    if cors.allow_credentials {
+        headers.insert(
+            ACCESS_CONTROL_ALLOW_CREDENTIALS,
+            HeaderValue::from_static("true"),
+        );
+    }
+}
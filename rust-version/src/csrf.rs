@@ -0,0 +1,85 @@
+//! src/csrf.rs
+//! Cross-site request forgery: a page of another site making the browser of an admin
+//! send a request to `/api/v1`, with the admin's credentials attached.
+//!
+//! Browsers cache HTTP Basic credentials (after our `Basic realm="zero2prod"` challenge) and
+//! attach them to every request to our origin, whoever the page sending it. Bearer tokens
+//! (the API token, API keys) are never attached by the browser on its own: they are exempt.
+//!
+//! So an unsafe request (anything but GET, HEAD, OPTIONS, TRACE) with Basic credentials is
+//! refused when a browser tells us it comes from another site:
+//! - `Origin`, sent by browsers along with every unsafe request: neither our own origin
+//!   nor one `cors` allows with credentials
+//! - `Sec-Fetch-Site: cross-site`, when there is no `Origin`
+//!
+//! Non-browser clients send neither header: they are not affected.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{AUTHORIZATION, ORIGIN};
+use actix_web::middleware::Next;
+use actix_web::{ResponseError, web};
+
+use crate::authentication::split_scheme;
+use crate::configuration::CorsSettings;
+use crate::problem::Problem;
+use crate::reload::LiveSettings;
+
+/// Middleware (`middleware::from_fn`) refusing the cross-site unsafe requests made with
+/// Basic credentials, before any handler (or body extractor) runs.
+pub async fn refuse_cross_site_passwords(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let settings = req
+        .app_data::<web::Data<LiveSettings>>()
+        .map(|live| live.current());
+    let uses_password = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(split_scheme)
+        .is_some_and(|(scheme, _)| scheme.eq_ignore_ascii_case("Basic"));
+    let refused = uses_password
+        && !req.method().is_safe()
+        && settings.is_some_and(|settings| is_cross_site(&req, &settings.cors));
+    if !refused {
+        return next.call(req).await.map(|res| res.map_into_left_body());
+    }
+
+    tracing::warn!(
+        origin = ?req.headers().get(ORIGIN),
+        "Cross-site request with Basic credentials refused"
+    );
+    let response = Problem::new(StatusCode::FORBIDDEN)
+        .with_type("cross-site-request")
+        .with_title("Cross-site request refused")
+        .with_detail(
+            "Password credentials are not accepted on requests from another site: \
+             use an API key (`Authorization: Bearer`)",
+        )
+        .with("code", "cross_site_request")
+        .error_response();
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+// Whether the browser sent `req` on behalf of a page of another site
+fn is_cross_site(req: &ServiceRequest, cors: &CorsSettings) -> bool {
+    let Some(origin) = req.headers().get(ORIGIN) else {
+        return req
+            .headers()
+            .get("Sec-Fetch-Site")
+            .is_some_and(|site| site.as_bytes().eq_ignore_ascii_case(b"cross-site"));
+    };
+    // e.g. `null`, for a sandboxed page: never ours
+    let origin = origin.to_str().unwrap_or_default();
+    // `scheme://host[:port]`, the host being what the browser sent in `Host`
+    let connection = req.connection_info();
+    let same_origin = origin
+        .split_once("://")
+        .is_some_and(|(_, host)| host.eq_ignore_ascii_case(connection.host()));
+    let trusted =
+        cors.allow_credentials && cors.allowed_origins.iter().any(|allowed| allowed == origin);
+    !(same_origin || trusted)
+}
@@ -0,0 +1,81 @@
+//! src/domain.rs
+//! Domain types, shared by the public routes and the `/api` ones.
+
+/// Where a subscriber stands in the double opt-in flow.
+/// Stored as `TEXT` in `subscriptions.status`.
+#[derive(
+    serde::Serialize, serde::Deserialize, utoipa::ToSchema, Clone, Copy, Debug, PartialEq, Eq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+}
+
+impl SubscriptionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::PendingConfirmation => "pending_confirmation",
+            SubscriptionStatus::Confirmed => "confirmed",
+        }
+    }
+}
+
+impl TryFrom<String> for SubscriptionStatus {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "pending_confirmation" => Ok(Self::PendingConfirmation),
+            "confirmed" => Ok(Self::Confirmed),
+            other => Err(format!("{} is not a valid subscription status", other)),
+        }
+    }
+}
+
+/// An email address in its canonical form, the one stored in `subscriptions.email`:
+/// trimmed, with its domain lowercased and IDNA-encoded (`bücher.example` -> `xn--bcher-kva.example`).
+///
+/// The local part is kept as typed: it MAY be case-sensitive (RFC 5321), though hardly ever is.
+/// Uniqueness ignores case altogether (`subscriptions_email_lower_key`), so do lookups:
+/// compare `lower(email)`, never `email`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubscriberEmail(String);
+
+impl SubscriberEmail {
+    pub fn parse(email: &str) -> Result<Self, String> {
+        let email = email.trim();
+        let invalid = || format!("{} is not a valid email address", email);
+        // The domain cannot contain `@`, a (quoted) local part can
+        let (local_part, domain) = email.rsplit_once('@').ok_or_else(invalid)?;
+        if local_part.is_empty() || local_part.contains(char::is_whitespace) {
+            return Err(invalid());
+        }
+        // UTS 46 processing: lowercases, normalizes, then punycodes the non-ASCII labels
+        let domain = idna::domain_to_ascii(domain.trim_end_matches('.')).map_err(|_| invalid())?;
+        // A bare hostname (`ursula@localhost`) does not receive newsletters
+        if !domain.contains('.') || domain.starts_with('.') || domain.ends_with('.') {
+            return Err(invalid());
+        }
+        Ok(Self(format!("{}@{}", local_part, domain)))
+    }
+
+    /// The part after the `@`, in ASCII.
+    pub fn domain(&self) -> &str {
+        self.0
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or_default()
+    }
+}
+
+impl AsRef<str> for SubscriberEmail {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for SubscriberEmail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,155 @@
+//! src/email_policy.rs
+//! Which email addresses may subscribe, evaluated before a subscriber is saved.
+//!
+//! In order:
+//! 1. role addresses (`postmaster@`, `abuse@`, ...) are refused: nobody in particular reads them
+//! 2. the operator's rules (`email_domain_rules` table, `/api/v1/email-domain-rules`):
+//!    `deny` refuses the domain, `allow` lets it through whatever step 3 says
+//! 3. the domains of the bundled disposable-provider list are refused
+//!
+//! A rule (or a listed domain) covers its subdomains too: `mailinator.com` covers
+//! `eu.mailinator.com`. When several rules match, the most specific domain wins.
+
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+use sqlx::PgPool;
+
+use crate::configuration::EmailPolicySettings;
+use crate::domain::SubscriberEmail;
+
+// Compiled into the binary: one domain per line, `#` comments
+static DISPOSABLE_DOMAINS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    include_str!("disposable_domains.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+});
+
+/// Why an address was refused.
+///
+/// `code` is part of the API contract (see `ApiError`): existing codes must never change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyViolation {
+    RoleAddress,
+    DeniedDomain,
+    DisposableDomain,
+}
+
+impl PolicyViolation {
+    pub fn code(&self) -> &'static str {
+        match self {
+            PolicyViolation::RoleAddress => "email_role_address",
+            PolicyViolation::DeniedDomain => "email_domain_denied",
+            PolicyViolation::DisposableDomain => "email_domain_disposable",
+        }
+    }
+}
+
+impl std::fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let detail = match self {
+            PolicyViolation::RoleAddress => "Role addresses (e.g. postmaster@) cannot subscribe",
+            PolicyViolation::DeniedDomain => "Addresses of this domain cannot subscribe",
+            PolicyViolation::DisposableDomain => "Disposable email addresses cannot subscribe",
+        };
+        write!(f, "{}", detail)
+    }
+}
+
+/// A rule of the `email_domain_rules` table. Stored as `TEXT` in `kind`.
+#[derive(
+    serde::Serialize, serde::Deserialize, utoipa::ToSchema, Clone, Copy, Debug, PartialEq, Eq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleKind {
+    Allow,
+    Deny,
+}
+
+impl RuleKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleKind::Allow => "allow",
+            RuleKind::Deny => "deny",
+        }
+    }
+}
+
+impl TryFrom<String> for RuleKind {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "allow" => Ok(Self::Allow),
+            "deny" => Ok(Self::Deny),
+            other => Err(format!("{} is not a valid email domain rule", other)),
+        }
+    }
+}
+
+/// The domain as rules are keyed: lowercase, IDNA-encoded (like `SubscriberEmail`),
+/// without the trailing dot of a FQDN.
+pub fn normalize_domain(domain: &str) -> String {
+    let domain = domain.trim().trim_end_matches('.');
+    // Not a valid domain: kept lowercase as is, it will not match any address anyway
+    idna::domain_to_ascii(domain).unwrap_or_else(|_| domain.to_lowercase())
+}
+
+/// The first violated step of the policy, if any.
+#[tracing::instrument(name = "Checking the email policy", skip(settings, db_conn))]
+pub async fn find_violation(
+    email: &SubscriberEmail,
+    settings: &EmailPolicySettings,
+    db_conn: &PgPool,
+) -> Result<Option<PolicyViolation>, sqlx::Error> {
+    // `postmaster+newsletter@` is still the postmaster
+    let local_part = email.as_ref().split(['+', '@']).next().unwrap_or_default();
+    if settings
+        .role_addresses
+        .iter()
+        .any(|role| role.eq_ignore_ascii_case(local_part))
+    {
+        return Ok(Some(PolicyViolation::RoleAddress));
+    }
+
+    let candidates = parent_domains(email.domain());
+    let rule = sqlx::query_scalar!(
+        r#"
+        SELECT kind FROM email_domain_rules
+        WHERE domain = ANY($1)
+        ORDER BY length(domain) DESC
+        LIMIT 1
+        "#,
+        &candidates
+    )
+    .fetch_optional(db_conn)
+    .await?
+    .map(RuleKind::try_from)
+    .transpose()
+    .map_err(|e| sqlx::Error::Decode(e.into()))?;
+
+    Ok(match rule {
+        Some(RuleKind::Deny) => Some(PolicyViolation::DeniedDomain),
+        Some(RuleKind::Allow) => None,
+        None if settings.block_disposable
+            && candidates
+                .iter()
+                .any(|d| DISPOSABLE_DOMAINS.contains(d.as_str())) =>
+        {
+            Some(PolicyViolation::DisposableDomain)
+        }
+        None => None,
+    })
+}
+
+// `eu.mailinator.com` -> [`eu.mailinator.com`, `mailinator.com`, `com`]
+fn parent_domains(domain: &str) -> Vec<String> {
+    let mut candidates = vec![domain.to_string()];
+    let mut rest = domain;
+    while let Some((_, parent)) = rest.split_once('.') {
+        candidates.push(parent.to_string());
+        rest = parent;
+    }
+    candidates
+}
@@ -0,0 +1,170 @@
+//! src/extractors.rs
+//! Custom extractors (i.e. types implementing `FromRequest`)
+
+use std::future::Future;
+use std::pin::Pin;
+
+use actix_web::http::StatusCode;
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError, dev, web};
+use serde::de::DeserializeOwned;
+
+use crate::problem::Problem;
+
+/// Body extractor negotiating on `Content-Type`:
+/// - `application/json` is decoded with `serde_json`
+/// - `application/x-www-form-urlencoded` is decoded with `serde_urlencoded`
+/// - anything else is rejected with `415 Unsupported Media Type`
+///
+/// SCALA: like an http4s `EntityDecoder` built with `orElse` over two media types.
+///
+/// Unlike `web::Json`/`web::Form`, decoding failures say which field was at fault.
+pub struct JsonOrForm<T>(pub T);
+
+impl<T> JsonOrForm<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> std::ops::Deref for JsonOrForm<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for JsonOrForm<T> {
+    type Error = actix_web::Error;
+    // An async block has an unnameable type, hence the boxed future.
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        let req = req.clone();
+        // Reading the body goes through `web::Bytes`: the payload size limits still apply.
+        let body = web::Bytes::from_request(&req, payload);
+        Box::pin(async move {
+            let decoded = match BodyFormat::of(&req) {
+                Some(BodyFormat::Json) => {
+                    let body = body.await?;
+                    let deserializer = &mut serde_json::Deserializer::from_slice(&body);
+                    serde_path_to_error::deserialize(deserializer).map_err(|e| {
+                        let path = e.path().to_string();
+                        let field = (path != ".").then_some(path);
+                        BodyError::malformed(field, e.into_inner().to_string())
+                    })?
+                }
+                Some(BodyFormat::Form) => {
+                    let body = body.await?;
+                    serde_urlencoded::from_bytes(&body)
+                        .map_err(|e| BodyError::malformed(None, e.to_string()))?
+                }
+                None => {
+                    return Err(BodyError::UnsupportedMediaType {
+                        content_type: req.content_type().to_string(),
+                    }
+                    .into());
+                }
+            };
+            Ok(JsonOrForm(decoded))
+        })
+    }
+}
+
+/// The body formats `JsonOrForm` decodes, read off the `Content-Type` of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyFormat {
+    Json,
+    Form,
+}
+
+impl BodyFormat {
+    /// Media types are case-insensitive (`Application/JSON` is JSON), parameters such as
+    /// `charset` are ignored, and a `+json` suffix (e.g. `application/merge-patch+json`)
+    /// is JSON too.
+    pub fn of(req: &impl HttpMessage) -> Option<Self> {
+        let mime = req.mime_type().ok()??;
+        let is = |name: actix_web::mime::Name, expected: &str| {
+            name.as_str().eq_ignore_ascii_case(expected)
+        };
+        if !is(mime.type_(), "application") {
+            None
+        } else if is(mime.subtype(), "json") || mime.suffix().is_some_and(|s| is(s, "json")) {
+            Some(BodyFormat::Json)
+        } else if is(mime.subtype(), "x-www-form-urlencoded") {
+            Some(BodyFormat::Form)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BodyError {
+    UnsupportedMediaType {
+        content_type: String,
+    },
+    Malformed {
+        field: Option<String>,
+        message: String,
+    },
+}
+
+impl BodyError {
+    fn malformed(field: Option<String>, message: String) -> Self {
+        // serde reports missing fields at the root of the document, naming them in its message:
+        // "missing field `email`"
+        let field = field.or_else(|| {
+            message
+                .strip_prefix("missing field `")
+                .and_then(|rest| rest.split('`').next())
+                .map(str::to_string)
+        });
+        Self::Malformed { field, message }
+    }
+
+    fn problem(&self) -> Problem {
+        match self {
+            BodyError::UnsupportedMediaType { content_type } => {
+                Problem::new(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+                    .with_type("unsupported-media-type")
+                    .with_detail(self.to_string())
+                    .with("content_type", content_type.as_str())
+            }
+            BodyError::Malformed { field, .. } => Problem::new(StatusCode::BAD_REQUEST)
+                .with_type("invalid-body")
+                .with_title("Invalid request body")
+                .with_detail(self.to_string())
+                .with("field", field.as_deref()),
+        }
+    }
+}
+
+impl std::fmt::Display for BodyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BodyError::UnsupportedMediaType { content_type } => write!(
+                f,
+                "Unsupported content type `{}`: use `application/json` or `application/x-www-form-urlencoded`",
+                content_type
+            ),
+            BodyError::Malformed {
+                field: Some(field),
+                message,
+            } => write!(f, "Invalid `{}` field: {}", field, message),
+            BodyError::Malformed { message, .. } => write!(f, "Invalid body: {}", message),
+        }
+    }
+}
+
+impl ResponseError for BodyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            BodyError::UnsupportedMediaType { .. } => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            BodyError::Malformed { .. } => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.problem().error_response()
+    }
+}
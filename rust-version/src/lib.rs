@@ -2,7 +2,26 @@
 //! Documents the module/crate itself
 //! Used at the top of files
 
+pub mod audit;
+pub mod authentication;
+pub mod bot_protection;
+pub mod cli;
 pub mod configuration;
+pub mod cors;
+pub mod csrf;
+pub mod domain;
+pub mod email_policy;
+pub mod extractors;
+pub mod listener;
+pub mod metrics;
+pub mod migrations;
+pub mod openapi;
+pub mod problem;
+pub mod rate_limit;
+pub mod reload;
 pub mod routes;
+pub mod secrets;
+pub mod security_headers;
 pub mod startup;
 pub mod telemetry;
+pub mod tls;
@@ -0,0 +1,104 @@
+//! src/listener.rs
+//! Where the app listens: TCP sockets, and/or Unix domain sockets (e.g. for a sidecar proxy).
+//!
+//! ```yaml
+//! server:
+//!   host: [127.0.0.1, "unix:/run/zero2prod/app.sock"]
+//! ```
+//!
+//! Unix sockets only exist on Unix (`#[cfg(unix)]`): elsewhere, `Settings::validate`
+//! refuses the `unix:` hosts up front.
+
+#[cfg(unix)]
+use std::fs::Permissions;
+#[cfg(unix)]
+use std::io::ErrorKind;
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
+
+/// An already bound socket, handed over to `startup::run`.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+/// Binds a Unix socket per path (see `ServerSettings::unix_socket_paths`).
+#[cfg(unix)]
+pub fn bind_unix_sockets(paths: &[PathBuf]) -> std::io::Result<Vec<Listener>> {
+    let mut listeners = vec![];
+    for path in paths {
+        let listener = bind_unix_socket(path).map_err(|e| {
+            std::io::Error::new(
+                e.kind(),
+                format!("Failed to bind to the socket {}: {}", path.display(), e),
+            )
+        })?;
+        tracing::info!("Listening on unix:{}", path.display());
+        listeners.push(Listener::Unix(listener));
+    }
+    Ok(listeners)
+}
+
+/// No Unix sockets on this platform: only an empty list of paths binds (to nothing).
+#[cfg(not(unix))]
+pub fn bind_unix_sockets(paths: &[PathBuf]) -> std::io::Result<Vec<Listener>> {
+    match paths.first() {
+        None => Ok(vec![]),
+        Some(path) => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!(
+                "Cannot bind to unix:{}: no Unix sockets on this platform",
+                path.display()
+            ),
+        )),
+    }
+}
+
+/// The mode of the socket file: read/write (i.e. connect) for its owner and group only.
+/// Run the sidecar proxy under the app's group to let it in.
+#[cfg(unix)]
+pub const UNIX_SOCKET_MODE: u32 = 0o660;
+
+/// Binds a Unix socket at `path`, replacing the socket file a previous run may have left behind.
+///
+/// Fails, rather than replacing it, when `path` is not a socket file or when a live process
+/// still answers on it (e.g. another instance of the app).
+#[cfg(unix)]
+pub fn bind_unix_socket(path: &Path) -> std::io::Result<UnixListener> {
+    remove_stale_socket(path)?;
+    let listener = UnixListener::bind(path)?;
+    // NOTE: the file is created with the umask mode, tightened (or loosened) right away
+    std::fs::set_permissions(path, Permissions::from_mode(UNIX_SOCKET_MODE))?;
+    Ok(listener)
+}
+
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> std::io::Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        other => other?,
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(std::io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+    // A socket file outlives the process which bound it (e.g. after a crash or a `kill -9`):
+    // nobody answering on it means it is stale.
+    if UnixStream::connect(path).is_ok() {
+        return Err(std::io::Error::new(
+            ErrorKind::AddrInUse,
+            format!("{} is in use by another process", path.display()),
+        ));
+    }
+    tracing::info!("Removing the stale socket {}", path.display());
+    std::fs::remove_file(path)
+}
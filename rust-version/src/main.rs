@@ -2,14 +2,23 @@
 //! Documents the module/crate itself
 //! Used at the top of files
 
-use std::net::TcpListener;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
 
+use clap::Parser;
 use secrecy::ExposeSecret;
 use sqlx::PgPool;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 
-use zero2prod::configuration::get_configuration;
-use zero2prod::startup::run;
+use zero2prod::cli::{self, Cli, Command, ConfigCommand, SubscribersCommand};
+use zero2prod::configuration::{Settings, config_dir, get_configuration};
+use zero2prod::listener::{Listener, bind_unix_sockets};
+use zero2prod::migrations::run_migrations;
+use zero2prod::rate_limit::RateLimiter;
+use zero2prod::reload::{ConfigReloader, LiveSettings};
+use zero2prod::startup::{run, run_redirect};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use zero2prod::tls::ReloadableCertificate;
 
 // Attribute macro: #[...] applies transformations to the item below (func, etc...)
 // tokio::main is a procedural macro that transforms async fn main() into a proper program entry point
@@ -17,26 +26,129 @@ use zero2prod::telemetry::{get_subscriber, init_subscriber};
 // Like IORuntime.global in cats-effect - without it, async code can't run
 #[tokio::main]
 async fn main() -> Result<(), std::io::Error> {
+    let command = Cli::parse().command.unwrap_or(Command::Serve);
+
+    // Configuration is read first: it decides how the logs should be formatted.
+    // A broken configuration is reported in full (`Display`), then we stop: nothing can run without it.
+    let config = get_configuration().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+
+    // The server logs to stdout, the other commands keep stdout for their own output.
+    // BoxMakeWriter erases the concrete writer type: both branches have the same type.
+    let sink = match command {
+        Command::Serve => BoxMakeWriter::new(std::io::stdout),
+        _ => BoxMakeWriter::new(std::io::stderr),
+    };
     let subscriber = get_subscriber(
         // .into() - Type conversion using Into trait.
         // Compiler infers target type from context.
         // Scala equivalent: implicit conversions, but explicit call in Rust
         "zero2prod".into(),
-        "info".into(),
-        std::io::stdout, // i.e the "sink" to which logs should be written
+        config.telemetry.level.clone(),
+        config.telemetry.format,
+        sink, // i.e the "sink" to which logs should be written
     );
 
     init_subscriber(subscriber);
 
-    let config = get_configuration().expect("Failed to read configuration.");
+    // Lazy: no connection is opened until a command actually queries the database
+    let db_conn_pool =
+        PgPool::connect_lazy(config.database.clone().connection_string().expose_secret())
+            .expect("Failed to connect to Postgres");
+
+    let out = &mut std::io::stdout();
+    let result = match command {
+        Command::Serve => return serve(config, db_conn_pool).await,
+        Command::Migrate { dry_run } => cli::migrate(&db_conn_pool, dry_run, out).await,
+        Command::CreateAdmin { username, role } => {
+            cli::create_admin(&db_conn_pool, &username, role, out).await
+        }
+        Command::ResetPassword { username } => {
+            cli::reset_password(&db_conn_pool, &username, out).await
+        }
+        Command::Subscribers { command } => match command {
+            SubscribersCommand::List { status } => {
+                cli::list_subscribers(&db_conn_pool, status, out).await
+            }
+            SubscribersCommand::Export => cli::export_subscribers(&db_conn_pool, out).await,
+            SubscribersCommand::Delete { subscriber } => {
+                cli::delete_subscriber(&db_conn_pool, &subscriber, out).await
+            }
+        },
+        Command::Config {
+            command: ConfigCommand::Check,
+        } => cli::config_check(&config, out),
+    };
+    result.map_err(std::io::Error::other)
+}
+
+async fn serve(config: Settings, db_conn_pool: PgPool) -> Result<(), std::io::Error> {
+    if config.database.migrate_on_startup {
+        run_migrations(&db_conn_pool)
+            .await
+            .map_err(std::io::Error::other)?;
+    }
+
+    // One listener per address: e.g. `127.0.0.1` and `::1`, served by the same workers
+    let unix_socket_paths = config.server.unix_socket_paths();
+    let listeners: Vec<Listener> = bind(config.server.tcp_socket_addresses()?)
+        .into_iter()
+        .map(Listener::Tcp)
+        .chain(bind_unix_sockets(&unix_socket_paths)?)
+        .collect();
 
-    let address = config.server.tcp_socket_address();
-    let error_msg = format!("Failed to bind to the address {:?}", address);
-    let listener = TcpListener::bind(&address).expect(&error_msg);
+    // HTTPS: the certificate is loaded (and watched) once, whatever the number of listeners
+    let mut tls = None;
+    let mut _certificate_watcher = None;
+    let mut redirect = None;
+    if let Some(tls_settings) = &config.server.tls {
+        let certificate = Arc::new(ReloadableCertificate::load(tls_settings)?);
+        tls = Some(certificate.server_config()?);
+        _certificate_watcher = Some(certificate.watch().map_err(std::io::Error::other)?);
+        if let Some(redirect_port) = tls_settings.redirect_port {
+            let redirect_listeners = bind(config.server.tcp_socket_addresses_on(redirect_port)?);
+            redirect = Some(run_redirect(redirect_listeners, config.server.port)?);
+        }
+    }
 
-    let db_conn_pool = PgPool::connect_lazy(config.database.connection_string().expose_secret())
-        .expect("Failed to connect to Postgres");
+    let rate_limiter = RateLimiter::new(config.rate_limit.store, &db_conn_pool);
+    let live_settings = Arc::new(LiveSettings::new(&config));
+    // Dropping the watcher would stop the reloads: it lives as long as the server
+    let _watcher = ConfigReloader::new(live_settings.clone(), config)
+        .watch(
+            &config_dir().map_err(std::io::Error::other)?,
+            get_configuration,
+        )
+        .map_err(std::io::Error::other)?;
+
+    let server = run(listeners, db_conn_pool, live_settings, tls, rate_limiter)?; // unwrapp the result of run() , i.e Result<Server, Error>
+    let outcome = match redirect {
+        // Both servers run concurrently, until either stops (e.g. on Ctrl-C, they both do)
+        Some(redirect) => {
+            tokio::select! {
+                outcome = server => outcome,
+                outcome = redirect => outcome,
+            }
+        }
+        None => server.await, // Actually executes the Server (Future) (like unsafeRunSync in cats-effect)
+    };
+    // A graceful shutdown leaves no socket file behind (the next start would remove it anyway)
+    for path in unix_socket_paths {
+        let _ = std::fs::remove_file(path);
+    }
+    outcome
+}
 
-    run(listener, db_conn_pool)? // unwrapp the result of run() , i.e Result<Server, Error>
-        .await // Actually executes the Server (Future) (like unsafeRunSync in cats-effect)
+fn bind(addresses: Vec<SocketAddr>) -> Vec<TcpListener> {
+    addresses
+        .into_iter()
+        .map(|address| {
+            let error_msg = format!("Failed to bind to the address {}", address);
+            let listener = TcpListener::bind(address).expect(&error_msg);
+            tracing::info!("Listening on {}", address);
+            listener
+        })
+        .collect()
 }
@@ -0,0 +1,83 @@
+//! src/metrics.rs
+//! Application metrics, exposed in the Prometheus text format (`GET /api/v1/metrics`).
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use actix_web::HttpResponse;
+use actix_web::http::header::ContentType;
+use actix_web::web;
+
+use crate::authentication::{Scoped, scopes};
+use crate::bot_protection::BotSignal;
+use crate::problem::Problem;
+
+/// The counters of the running app, registered as application state in `startup::run`.
+///
+/// One instance per app (rather than a global): each test app counts on its own.
+pub struct Metrics {
+    // One counter per `BotSignal`, in `BotSignal::ALL` order
+    bot_rejections: [AtomicU64; BotSignal::ALL.len()],
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            bot_rejections: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn record_bot_rejection(&self, signal: BotSignal) {
+        // Relaxed: a counter needs no ordering with respect to anything else
+        self.bot_rejections[signal as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn bot_rejections(&self, signal: BotSignal) -> u64 {
+        self.bot_rejections[signal as usize].load(Ordering::Relaxed)
+    }
+
+    /// https://prometheus.io/docs/instrumenting/exposition_formats/
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        text.push_str(
+            "# HELP zero2prod_bot_rejections_total Signup submissions dropped as coming from bots\n",
+        );
+        text.push_str("# TYPE zero2prod_bot_rejections_total counter\n");
+        for signal in BotSignal::ALL {
+            // Zeroes included: a series which is missing cannot be told from a broken scrape
+            let _ = writeln!(
+                text,
+                "zero2prod_bot_rejections_total{{reason=\"{}\"}} {}",
+                signal.as_str(),
+                self.bot_rejections(signal)
+            );
+        }
+        text
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/metrics",
+    tag = "operations",
+    security(("api_token" = ["metrics:read"]), ("admin_user" = ["metrics:read"])),
+    responses(
+        (status = 200, description = "Prometheus text exposition format", body = String, content_type = "text/plain; version=0.0.4"),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `metrics:read` scope (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+pub async fn metrics(
+    _caller: Scoped<scopes::MetricsRead>,
+    metrics: web::Data<Metrics>,
+) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType(
+            "text/plain; version=0.0.4"
+                .parse()
+                .expect("A valid media type"),
+        ))
+        .body(metrics.render())
+}
@@ -0,0 +1,43 @@
+//! src/migrations.rs
+//! The SQL files in `migrations/` are embedded into the binary at compile time:
+//! the production image can migrate its database without `sqlx-cli` (see `zero2prod migrate`).
+
+use sqlx::PgPool;
+use sqlx::migrate::{Migrate, MigrateError, Migration, Migrator};
+
+// `migrate!` reads the directory at COMPILE time (path relative to the Cargo.toml)
+// `build.rs` makes sure a new migration file triggers a rebuild.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Applies every pending migration.
+///
+/// Safe to call from several replicas starting at the same time:
+/// `Migrator::run` holds a Postgres advisory lock (`pg_advisory_lock`) for the whole run,
+/// the other callers wait for it, then find nothing left to apply.
+#[tracing::instrument(name = "Running database migrations", skip(db_conn_pool))]
+pub async fn run_migrations(db_conn_pool: &PgPool) -> Result<(), MigrateError> {
+    MIGRATOR.run(db_conn_pool).await
+}
+
+/// The embedded migrations not applied yet to the database, in order (nothing is written).
+pub async fn pending_migrations(
+    db_conn_pool: &PgPool,
+) -> Result<Vec<&'static Migration>, MigrateError> {
+    let mut db_conn = db_conn_pool.acquire().await?;
+    // The bookkeeping table is only created by the first run: a brand new database has none.
+    let table_exists: bool =
+        sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+            .fetch_one(&mut *db_conn)
+            .await?;
+    let applied = if table_exists {
+        db_conn.list_applied_migrations().await?
+    } else {
+        vec![]
+    };
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+        .filter(|migration| !applied.iter().any(|a| a.version == migration.version))
+        .collect())
+}
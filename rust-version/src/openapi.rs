@@ -0,0 +1,105 @@
+//! src/openapi.rs
+//! OpenAPI 3 document, generated at compile time from the `#[utoipa::path]` annotations
+//! on the handlers and the `ToSchema`/`IntoParams` derives on the request/response types.
+//!
+//! The committed copy (`openapi.json`, at the root of the repo) is what the frontend and the
+//! Scala twin code against: `tests/openapi.rs` fails whenever it drifts from the generated one.
+
+use actix_web::{HttpResponse, web};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::routes;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "zero2prod",
+        description = "Newsletter service: public subscription form and subscriber management API"
+    ),
+    paths(
+        routes::health_check,
+        routes::subscribe,
+        routes::form_token,
+        routes::api::list_subscribers,
+        routes::api::get_subscriber,
+        routes::api::create_subscriber,
+        routes::api::update_subscriber,
+        routes::api::delete_subscriber,
+        routes::api::list_email_domain_rules,
+        routes::api::get_email_domain_rule,
+        routes::api::create_email_domain_rule,
+        routes::api::update_email_domain_rule,
+        routes::api::delete_email_domain_rule,
+        routes::api::list_api_keys,
+        routes::api::create_api_key,
+        routes::api::revoke_api_key,
+        routes::api::list_users,
+        routes::api::update_user_role,
+        crate::metrics::metrics,
+    ),
+    components(schemas(crate::problem::Problem)),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "public", description = "Unauthenticated endpoints"),
+        (name = "subscribers", description = "Subscriber management (`/api/v1`)"),
+        (name = "email domain rules", description = "Exceptions to the email domain policy (`/api/v1`)"),
+        (name = "api keys", description = "Credentials of machine-to-machine callers (`/api/v1`)"),
+        (name = "users", description = "Roles of the admins (`/api/v1`)"),
+        (name = "operations", description = "Monitoring (`/api/v1`)")
+    )
+)]
+pub struct ApiDoc;
+
+// Declares the `api_token` and `admin_user` security schemes referenced by the `/api/v1` paths,
+// each listing the scope it requires (see `authentication::Scoped`).
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "An API key (`z2p_...`, see `/api/v1/api-keys`), or the configured `api.token`",
+                    ))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "admin_user",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Basic)
+                    .description(Some(
+                        "An admin's username and password: granted the scopes of its role \
+                        (`analyst`: `subscribers:read`, `metrics:read`; \
+                        `editor`: also `subscribers:write`, `email_domain_rules:manage`; \
+                        `owner`: every scope). Refused with a `403` (`cross_site_request`) \
+                        on writes a browser sends from another site",
+                    ))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// `GET /openapi.json`
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+/// Registers the interactive docs UI at `/docs`.
+/// A no-op unless the crate is built with the `docs-ui` feature.
+pub fn docs_ui(cfg: &mut web::ServiceConfig) {
+    #[cfg(feature = "docs-ui")]
+    {
+        use utoipa_scalar::{Scalar, Servable};
+        cfg.service(Scalar::with_url("/docs", ApiDoc::openapi()));
+    }
+    #[cfg(not(feature = "docs-ui"))]
+    let _ = cfg;
+}
@@ -0,0 +1,192 @@
+//! src/problem.rs
+//! RFC 7807 "Problem Details for HTTP APIs", i.e. `application/problem+json` error bodies.
+//!
+//! Every error response leaving the app goes through `render_problem` (see `startup`):
+//! - errors implementing `ResponseError` describe themselves with a `Problem`
+//! - anything else (extractor failures, 404, 405, 413, ...) gets a generic `Problem`
+//!   built from its status code.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::ServiceResponse;
+use actix_web::error::{JsonPayloadError, PathError, QueryPayloadError, UrlencodedError};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{CONTENT_TYPE, HeaderValue};
+use actix_web::middleware::ErrorHandlerResponse;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use tracing_actix_web::RequestId;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// RFC 7807 problem details.
+///
+/// Problem types may add extension members, e.g. `code` (`/api/v1` errors)
+/// or `field` (malformed bodies).
+#[derive(Debug, Clone, serde::Serialize, utoipa::ToSchema)]
+pub struct Problem {
+    // A URI reference identifying the problem type.
+    // `about:blank` means "nothing more specific than the status code".
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    // RFC 7807 allows problem types to define extra members (e.g. the faulty `field`)
+    #[serde(flatten)]
+    #[schema(ignore)]
+    pub extensions: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            kind: "about:blank".into(),
+            title: status.canonical_reason().unwrap_or("Error").into(),
+            status: status.as_u16(),
+            detail: None,
+            request_id: None,
+            extensions: serde_json::Map::new(),
+        }
+    }
+
+    /// Set a problem type, relative to `/problems/` (e.g. `invalid-body`).
+    pub fn with_type(mut self, slug: &str) -> Self {
+        self.kind = format!("/problems/{}", slug);
+        self
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Add an extension member to the problem.
+    pub fn with(mut self, key: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.extensions.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn to_body(&self) -> String {
+        serde_json::to_string(self).expect("A problem is always serializable")
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.detail {
+            Some(detail) => write!(f, "{}: {}", self.title, detail),
+            None => write!(f, "{}", self.title),
+        }
+    }
+}
+
+impl ResponseError for Problem {
+    fn status_code(&self) -> StatusCode {
+        self.status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code())
+            .insert_header((CONTENT_TYPE, PROBLEM_JSON))
+            .body(self.to_body());
+        // Stashed so that `render_problem` can re-render it with the request id.
+        response.extensions_mut().insert(self.clone());
+        response
+    }
+}
+
+/// `ErrorHandlers` default handler: (re-)render every 4xx/5xx response as `application/problem+json`.
+///
+/// Headers set by the original response (e.g. `Allow`, `Retry-After`) are preserved.
+pub fn render_problem<B: MessageBody>(
+    response: ServiceResponse<B>,
+) -> actix_web::Result<ErrorHandlerResponse<B>> {
+    let (request, response) = response.into_parts();
+    let problem = response
+        .extensions()
+        .get::<Problem>()
+        .cloned()
+        .unwrap_or_else(|| {
+            let problem = Problem::new(response.status());
+            match response.error() {
+                // Server errors could leak internals (SQL, file paths, ...): no detail for them.
+                Some(error) if response.status().is_client_error() => {
+                    problem.with_detail(error.to_string())
+                }
+                _ => problem,
+            }
+        });
+    let problem = Problem {
+        request_id: request
+            .extensions()
+            .get::<RequestId>()
+            .map(|id| id.to_string()),
+        ..problem
+    };
+
+    let mut response = response.set_body(problem.to_body());
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+    let response = ServiceResponse::new(request, response)
+        .map_into_boxed_body()
+        .map_into_right_body();
+    Ok(ErrorHandlerResponse::Response(response))
+}
+
+// Error handlers for actix-web's built-in extractors, registered via their `*Config` in `startup`.
+// They replace actix-web's plain-text rejections with problems.
+
+pub fn json_error_handler(error: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let problem = match &error {
+        JsonPayloadError::ContentType => Problem::new(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+            Problem::new(StatusCode::PAYLOAD_TOO_LARGE)
+        }
+        _ => Problem::new(StatusCode::BAD_REQUEST)
+            .with_type("invalid-body")
+            .with_title("Invalid request body"),
+    };
+    problem.with_detail(error.to_string()).into()
+}
+
+pub fn form_error_handler(error: UrlencodedError, _req: &HttpRequest) -> actix_web::Error {
+    let problem = match &error {
+        UrlencodedError::ContentType => Problem::new(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+        UrlencodedError::Overflow { .. } => Problem::new(StatusCode::PAYLOAD_TOO_LARGE),
+        _ => Problem::new(StatusCode::BAD_REQUEST)
+            .with_type("invalid-body")
+            .with_title("Invalid request body"),
+    };
+    problem.with_detail(error.to_string()).into()
+}
+
+pub fn query_error_handler(error: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    Problem::new(StatusCode::BAD_REQUEST)
+        .with_type("invalid-query")
+        .with_title("Invalid query string")
+        .with_detail(error.to_string())
+        .into()
+}
+
+pub fn path_error_handler(error: PathError, _req: &HttpRequest) -> actix_web::Error {
+    Problem::new(StatusCode::NOT_FOUND)
+        .with_detail(error.to_string())
+        .into()
+}
+
+/// Default service: any request that did not match a route.
+pub async fn not_found() -> Result<HttpResponse, Problem> {
+    Err(Problem::new(StatusCode::NOT_FOUND))
+}
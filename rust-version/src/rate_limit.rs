@@ -0,0 +1,426 @@
+//! src/rate_limit.rs
+//! Rate limiting of `POST /subscription`, so that bots can neither flood the `subscriptions`
+//! table nor use us to spam third parties.
+//!
+//! Two token buckets are checked for every request (see `RateLimitSettings`):
+//! one keyed by client IP, one keyed by the (normalized) email address to subscribe.
+//! Over the limit, the request is rejected with `429 Too Many Requests` and a `Retry-After`.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::middleware::Next;
+use actix_web::{ResponseError, web};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::configuration::{BucketSettings, RateLimitStore};
+use crate::domain::SubscriberEmail;
+use crate::extractors::BodyFormat;
+use crate::problem::Problem;
+use crate::reload::LiveSettings;
+
+// Beyond that many buckets in memory, some are dropped (see `evict`)
+const MAX_MEMORY_BUCKETS: usize = 10_000;
+
+// A bucket left alone that long is full again (or as good as): it can be forgotten
+// (Postgres store only: in memory, each bucket knows when it is full again)
+fn stale_before(now: DateTime<Utc>) -> DateTime<Utc> {
+    now - chrono::Duration::days(1)
+}
+
+/// Where the buckets live, chosen at startup (`rate_limit.store`).
+///
+/// An enum rather than a trait object: `async fn`s in traits cannot be called through `dyn`.
+pub enum RateLimiter {
+    Memory(Mutex<HashMap<String, MemoryBucket>>),
+    Postgres(PgPool),
+}
+
+/// The outcome of a check.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+/// A token bucket: `tokens` as of `updated_at`, refilled lazily on the next check.
+#[derive(Debug, Clone, Copy)]
+pub struct Bucket {
+    tokens: f64,
+    updated_at: DateTime<Utc>,
+}
+
+impl Bucket {
+    fn full(limit: &BucketSettings, now: DateTime<Utc>) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            updated_at: now,
+        }
+    }
+
+    // The bucket after the refill since `updated_at`, and after taking a token (if any is left)
+    fn take(self, limit: &BucketSettings, now: DateTime<Utc>) -> (Bucket, Decision) {
+        let per_second = limit.per_hour as f64 / 3600.0;
+        let elapsed = (now - self.updated_at).num_milliseconds().max(0) as f64 / 1000.0;
+        // Never more than `burst`, however long the bucket was left alone
+        let tokens = (self.tokens + elapsed * per_second).min(limit.burst as f64);
+        if tokens >= 1.0 {
+            let bucket = Bucket {
+                tokens: tokens - 1.0,
+                updated_at: now,
+            };
+            (bucket, Decision::Allowed)
+        } else {
+            // The time left until the next whole token
+            let retry_after = Duration::from_secs_f64((1.0 - tokens) / per_second);
+            let bucket = Bucket {
+                tokens,
+                updated_at: now,
+            };
+            (bucket, Decision::Limited { retry_after })
+        }
+    }
+}
+
+/// A bucket of the memory store, and when it will be full again.
+///
+/// A full bucket carries no information (it is what an unknown key gets): it can be dropped.
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryBucket {
+    bucket: Bucket,
+    full_at: DateTime<Utc>,
+}
+
+impl RateLimiter {
+    pub fn new(store: RateLimitStore, db_conn_pool: &PgPool) -> Self {
+        match store {
+            RateLimitStore::Memory => Self::Memory(Mutex::new(HashMap::new())),
+            RateLimitStore::Postgres => Self::Postgres(db_conn_pool.clone()),
+        }
+    }
+
+    /// Takes a token from the bucket of `key`, if there is one left.
+    pub async fn check(&self, key: &str, limit: &BucketSettings) -> Result<Decision, sqlx::Error> {
+        let now = Utc::now();
+        match self {
+            RateLimiter::Memory(buckets) => {
+                let mut buckets = buckets.lock().unwrap();
+                Ok(check_in_memory(&mut buckets, key, limit, now))
+            }
+            RateLimiter::Postgres(pool) => check_in_postgres(pool, key, limit, now).await,
+        }
+    }
+
+    /// Uses up `key` until `expires_at`: `false` when it was already used (e.g. a form token,
+    /// see `bot_protection`). A bucket of a single token, empty until `expires_at`.
+    pub async fn spend(&self, key: &str, expires_at: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+        let now = Utc::now();
+        match self {
+            RateLimiter::Memory(buckets) => {
+                let mut buckets = buckets.lock().unwrap();
+                Ok(spend_in_memory(&mut buckets, key, expires_at, now))
+            }
+            RateLimiter::Postgres(pool) => spend_in_postgres(pool, key, expires_at).await,
+        }
+    }
+}
+
+fn check_in_memory(
+    buckets: &mut HashMap<String, MemoryBucket>,
+    key: &str,
+    limit: &BucketSettings,
+    now: DateTime<Utc>,
+) -> Decision {
+    let bucket = match buckets.get(key) {
+        Some(known) => known.bucket,
+        None => {
+            if buckets.len() >= MAX_MEMORY_BUCKETS {
+                evict(buckets, now);
+            }
+            Bucket::full(limit, now)
+        }
+    };
+    let (bucket, decision) = bucket.take(limit, now);
+    let per_second = limit.per_hour as f64 / 3600.0;
+    let refill = (limit.burst as f64 - bucket.tokens).max(0.0) / per_second;
+    let full_at = now + chrono::Duration::milliseconds((refill * 1000.0).ceil() as i64);
+    buckets.insert(key.to_string(), MemoryBucket { bucket, full_at });
+    decision
+}
+
+fn spend_in_memory(
+    buckets: &mut HashMap<String, MemoryBucket>,
+    key: &str,
+    expires_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> bool {
+    if buckets.get(key).is_some_and(|known| known.full_at > now) {
+        return false;
+    }
+    if buckets.len() >= MAX_MEMORY_BUCKETS {
+        evict(buckets, now);
+    }
+    let bucket = Bucket {
+        tokens: 0.0,
+        updated_at: now,
+    };
+    buckets.insert(
+        key.to_string(),
+        MemoryBucket {
+            bucket,
+            full_at: expires_at,
+        },
+    );
+    true
+}
+
+// Down to half the capacity, so that the next eviction is `MAX_MEMORY_BUCKETS / 2` new keys
+// away: its cost is spread over them (not paid by every request of a flood).
+// The full buckets go first, then the ones closest to full: a key forgotten early only gets
+// back a few tokens it would have had shortly anyway.
+fn evict(buckets: &mut HashMap<String, MemoryBucket>, now: DateTime<Utc>) {
+    buckets.retain(|_, known| known.full_at > now);
+    let excess = buckets.len().saturating_sub(MAX_MEMORY_BUCKETS / 2);
+    if excess == 0 {
+        return;
+    }
+    let mut by_full_at: Vec<(DateTime<Utc>, String)> = buckets
+        .iter()
+        .map(|(key, known)| (known.full_at, key.clone()))
+        .collect();
+    by_full_at.select_nth_unstable(excess - 1);
+    for (_, key) in &by_full_at[..excess] {
+        buckets.remove(key);
+    }
+}
+
+// The row is locked (`FOR UPDATE`) from the read to the write:
+// concurrent checks of the same key, from any replica, take their tokens one after the other.
+async fn check_in_postgres(
+    pool: &PgPool,
+    key: &str,
+    limit: &BucketSettings,
+    now: DateTime<Utc>,
+) -> Result<Decision, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let full = Bucket::full(limit, now);
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO rate_limit_buckets (key, tokens, updated_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (key) DO NOTHING
+        "#,
+        key,
+        full.tokens,
+        full.updated_at
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if inserted > 0 {
+        // A new key: time to forget the stale ones
+        sqlx::query!(
+            "DELETE FROM rate_limit_buckets WHERE updated_at < $1",
+            stale_before(now)
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    let row = sqlx::query!(
+        "SELECT tokens, updated_at FROM rate_limit_buckets WHERE key = $1 FOR UPDATE",
+        key
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    let (bucket, decision) = Bucket {
+        tokens: row.tokens,
+        updated_at: row.updated_at,
+    }
+    .take(limit, now);
+    sqlx::query!(
+        "UPDATE rate_limit_buckets SET tokens = $2, updated_at = $3 WHERE key = $1",
+        key,
+        bucket.tokens,
+        bucket.updated_at
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(decision)
+}
+
+// Only the first use inserts the row. Its `updated_at` is `expires_at`, in the future:
+// the stale rows pruning (`stale_before`) keeps it at least until then.
+async fn spend_in_postgres(
+    pool: &PgPool,
+    key: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO rate_limit_buckets (key, tokens, updated_at)
+        VALUES ($1, 0, $2)
+        ON CONFLICT (key) DO NOTHING
+        "#,
+        key,
+        expires_at
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(inserted > 0)
+}
+
+/// `a@Example.COM ` and `a@example.com` are the same mailbox, as far as limits go:
+/// the canonical form (`SubscriberEmail`), lowercased like the uniqueness of `subscriptions`.
+pub fn normalize_email(email: &str) -> String {
+    match SubscriberEmail::parse(email) {
+        Ok(email) => email.as_ref().to_lowercase(),
+        // Rejected by the handler anyway, but limited all the same
+        Err(_) => email.trim().to_lowercase(),
+    }
+}
+
+// Only the `email` of the body is of interest here
+#[derive(serde::Deserialize)]
+struct EmailField {
+    email: String,
+}
+
+/// Middleware (`middleware::from_fn`) enforcing the limits on the resource it wraps.
+///
+/// The body is read (to find the email) and put back for the handler:
+/// a malformed one goes through unchecked by email, the handler rejects it anyway.
+pub async fn rate_limit(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let (Some(limiter), Some(live)) = (
+        req.app_data::<web::Data<RateLimiter>>().cloned(),
+        req.app_data::<web::Data<LiveSettings>>().cloned(),
+    ) else {
+        return next.call(req).await.map(|res| res.map_into_left_body());
+    };
+    let settings = live.current();
+
+    let mut keys = vec![];
+    // Same resolution as the access log: `X-Forwarded-For` only from trusted proxies.
+    // NOTE: unknown over a Unix socket, the per-email limit still applies.
+    let forwarded_for = req
+        .headers()
+        .get("X-Forwarded-For")
+        .and_then(|h| h.to_str().ok());
+    if let Some(ip) = settings
+        .trusted_proxies
+        .client_ip(req.peer_addr().map(|addr| addr.ip()), forwarded_for)
+    {
+        keys.push((format!("ip:{}", ip), settings.rate_limit.per_ip));
+    }
+    let body = match req.extract::<web::Bytes>().await {
+        Ok(body) => body,
+        // e.g. `413 Payload Too Large`: a response (not an `Err`), to be rendered as a problem
+        Err(e) => return Ok(req.error_response(e).map_into_right_body()),
+    };
+    let email = match BodyFormat::of(&req) {
+        Some(BodyFormat::Json) => serde_json::from_slice::<EmailField>(&body).ok(),
+        Some(BodyFormat::Form) => serde_urlencoded::from_bytes(&body).ok(),
+        None => None,
+    };
+    if let Some(EmailField { email }) = email {
+        keys.push((
+            format!("email:{}", normalize_email(&email)),
+            settings.rate_limit.per_email,
+        ));
+    }
+    req.set_payload(actix_web::dev::Payload::from(body));
+
+    for (key, limit) in keys {
+        match limiter.check(&key, &limit).await {
+            Ok(Decision::Allowed) => {}
+            Ok(Decision::Limited { retry_after }) => {
+                tracing::warn!(key = %key, "Rate limit exceeded");
+                let response = too_many_requests(retry_after);
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+            // Better to let a few requests too many through than to turn everyone away
+            Err(e) => tracing::error!(error = %e, "Failed to check the rate limit"),
+        }
+    }
+    next.call(req).await.map(|res| res.map_into_left_body())
+}
+
+fn too_many_requests(retry_after: Duration) -> actix_web::HttpResponse {
+    // Whole seconds, rounded up: retrying on the dot must succeed
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let mut response = Problem::new(StatusCode::TOO_MANY_REQUESTS)
+        .with_type("rate-limited")
+        .with_detail(format!("Too many attempts, retry in {} second(s)", seconds))
+        .error_response();
+    response.headers_mut().insert(RETRY_AFTER, seconds.into());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::Utc;
+
+    use super::{Decision, MAX_MEMORY_BUCKETS, check_in_memory};
+    use crate::configuration::BucketSettings;
+
+    fn limit() -> BucketSettings {
+        BucketSettings {
+            burst: 2,
+            per_hour: 60,
+        }
+    }
+
+    #[test]
+    fn the_memory_store_stays_bounded_under_many_distinct_keys() {
+        let mut buckets = HashMap::new();
+        let now = Utc::now();
+
+        // A flood of new keys, within the same instant: none of their buckets is full again
+        for i in 0..(5 * MAX_MEMORY_BUCKETS) {
+            check_in_memory(&mut buckets, &format!("bot-{}", i), &limit(), now);
+            assert!(buckets.len() <= MAX_MEMORY_BUCKETS);
+        }
+    }
+
+    #[test]
+    fn eviction_spares_the_buckets_still_being_drained() {
+        let mut buckets = HashMap::new();
+        let now = Utc::now();
+        // Drained just before the flood: full again in two minutes
+        for _ in 0..2 {
+            check_in_memory(&mut buckets, "drained", &limit(), now);
+        }
+        let flood_start = now + chrono::Duration::seconds(1);
+        // Took a single token of 2, long ago: full again by now
+        for i in 0..(MAX_MEMORY_BUCKETS - 1) {
+            check_in_memory(
+                &mut buckets,
+                &format!("old-{}", i),
+                &limit(),
+                now - chrono::Duration::hours(1),
+            );
+        }
+
+        // ACT
+        check_in_memory(&mut buckets, "newcomer", &limit(), flood_start);
+
+        // ASSERT
+        assert_eq!(buckets.len(), 2);
+        assert!(matches!(
+            check_in_memory(&mut buckets, "drained", &limit(), flood_start),
+            Decision::Limited { .. }
+        ));
+    }
+}
@@ -0,0 +1,248 @@
+//! src/reload.rs
+//! Configuration hot reload: edit `configuration/*.yaml`, no restart needed.
+//!
+//! Only the NON-STRUCTURAL settings can change at runtime (`RuntimeSettings`):
+//! the listener address and the database credentials are fixed at startup,
+//! a change to them is logged and ignored until the next restart.
+//!
+//! A reloaded configuration goes through the same validation as at startup:
+//! an invalid one is rejected (and logged) while the app keeps running on the previous one.
+
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use secrecy::ExposeSecret;
+
+use crate::configuration::{
+    ApiSettings, BotProtectionSettings, ConfigurationError, CorsSettings, EmailPolicySettings,
+    RateLimitSettings, Settings,
+};
+use crate::telemetry::{TrustedProxies, reload_log_level};
+
+/// The subset of `Settings` read by the running app, and replaced on reload.
+#[derive(Debug, Clone)]
+pub struct RuntimeSettings {
+    pub api: ApiSettings,
+    pub trusted_proxies: TrustedProxies,
+    // NOTE: `store` is structural, only the limits are read from here
+    pub rate_limit: RateLimitSettings,
+    pub bot_protection: BotProtectionSettings,
+    pub email_policy: EmailPolicySettings,
+    pub cors: CorsSettings,
+}
+
+impl RuntimeSettings {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            api: settings.api.clone(),
+            trusted_proxies: TrustedProxies::new(settings.server.trusted_proxies.clone()),
+            rate_limit: settings.rate_limit.clone(),
+            bot_protection: settings.bot_protection.clone(),
+            email_policy: settings.email_policy.clone(),
+            cors: settings.cors.clone(),
+        }
+    }
+}
+
+/// The current `RuntimeSettings`, shared by every worker (registered as app data).
+///
+/// `ArcSwap`: readers get the current `Arc` without taking any lock,
+/// a reload swaps in a whole new one atomically (a request never sees half of a reload).
+/// Scala equivalent: an `AtomicReference[RuntimeSettings]` (or a cats-effect `Ref`)
+pub struct LiveSettings(ArcSwap<RuntimeSettings>);
+
+impl LiveSettings {
+    pub fn new(settings: &Settings) -> Self {
+        Self(ArcSwap::from_pointee(RuntimeSettings::new(settings)))
+    }
+
+    pub fn current(&self) -> Arc<RuntimeSettings> {
+        self.0.load_full()
+    }
+}
+
+/// Applies reloaded configurations to the `LiveSettings` (and to the log level).
+pub struct ConfigReloader {
+    live: Arc<LiveSettings>,
+    // The last configuration applied, to tell what changed
+    applied: Mutex<Settings>,
+}
+
+impl ConfigReloader {
+    pub fn new(live: Arc<LiveSettings>, settings: Settings) -> Self {
+        Self {
+            live,
+            applied: Mutex::new(settings),
+        }
+    }
+
+    /// Applies the reloadable part of `loaded`, returning the description of what changed.
+    /// An invalid configuration is returned as is: nothing is applied.
+    pub fn apply(
+        &self,
+        loaded: Result<Settings, ConfigurationError>,
+    ) -> Result<Vec<String>, ConfigurationError> {
+        let mut settings = loaded?;
+        let mut applied = self.applied.lock().unwrap();
+
+        for key in structural_changes(&applied, &settings) {
+            tracing::warn!("`{}` changed: ignored until the next restart", key);
+        }
+        // Keep running with the structural settings of the startup
+        settings.server.host = applied.server.host.clone();
+        settings.server.port = applied.server.port;
+        settings.server.tls = applied.server.tls.clone();
+        settings.database = applied.database.clone();
+        settings.telemetry.format = applied.telemetry.format;
+        settings.rate_limit.store = applied.rate_limit.store;
+
+        let changes = reloadable_changes(&applied, &settings);
+        if changes.is_empty() {
+            return Ok(changes);
+        }
+        if settings.telemetry.level != applied.telemetry.level {
+            // NOTE: the level was validated with the rest of the configuration
+            if let Err(e) = reload_log_level(&settings.telemetry.level) {
+                tracing::error!("Failed to change the log level: {}", e);
+            }
+        }
+        self.live.0.store(Arc::new(RuntimeSettings::new(&settings)));
+        *applied = settings;
+        Ok(changes)
+    }
+
+    /// Re-runs `load` whenever a file of `config_dir` changes, applying the result.
+    ///
+    /// Watching stops when the returned watcher is dropped: keep it alive.
+    pub fn watch<F>(self, config_dir: &Path, load: F) -> notify::Result<RecommendedWatcher>
+    where
+        F: Fn() -> Result<Settings, ConfigurationError> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(config_dir, RecursiveMode::NonRecursive)?;
+
+        std::thread::spawn(move || {
+            // The loop (and the thread) ends once the watcher, i.e. the sender, is dropped
+            while let Ok(event) = rx.recv() {
+                if event.is_err() {
+                    continue;
+                }
+                // Saving a file usually fires several events (truncate, write, rename...):
+                // wait for things to settle, then reload once.
+                while rx.recv_timeout(Duration::from_millis(200)).is_ok() {}
+                match self.apply(load()) {
+                    Ok(changes) if changes.is_empty() => {}
+                    Ok(changes) => {
+                        tracing::info!(changes = %changes.join(", "), "Configuration reloaded")
+                    }
+                    Err(e) => tracing::error!(error = %e, "Configuration reload rejected"),
+                }
+            }
+        });
+        Ok(watcher)
+    }
+}
+
+fn structural_changes(old: &Settings, new: &Settings) -> Vec<&'static str> {
+    let mut changes = vec![];
+    if old.server.host != new.server.host {
+        changes.push("server.host");
+    }
+    if old.server.port != new.server.port {
+        changes.push("server.port");
+    }
+    // NOTE: the certificate itself is reloaded by `tls::ReloadableCertificate`, not its paths
+    if old.server.tls != new.server.tls {
+        changes.push("server.tls");
+    }
+    if old.database.clone().connection_string().expose_secret()
+        != new.database.clone().connection_string().expose_secret()
+        || old.database.migrate_on_startup != new.database.migrate_on_startup
+    {
+        changes.push("database");
+    }
+    if old.telemetry.format != new.telemetry.format {
+        changes.push("telemetry.format");
+    }
+    if old.rate_limit.store != new.rate_limit.store {
+        changes.push("rate_limit.store");
+    }
+    changes
+}
+
+// e.g. `telemetry.level: info -> debug` (secrets are never printed, only flagged as changed)
+fn reloadable_changes(old: &Settings, new: &Settings) -> Vec<String> {
+    let mut changes = vec![];
+    if old.telemetry.level != new.telemetry.level {
+        changes.push(format!(
+            "telemetry.level: {} -> {}",
+            old.telemetry.level, new.telemetry.level
+        ));
+    }
+    if old.server.trusted_proxies != new.server.trusted_proxies {
+        changes.push(format!(
+            "server.trusted_proxies: {:?} -> {:?}",
+            old.server.trusted_proxies, new.server.trusted_proxies
+        ));
+    }
+    for (key, old_limit, new_limit) in [
+        (
+            "rate_limit.per_ip",
+            old.rate_limit.per_ip,
+            new.rate_limit.per_ip,
+        ),
+        (
+            "rate_limit.per_email",
+            old.rate_limit.per_email,
+            new.rate_limit.per_email,
+        ),
+    ] {
+        if old_limit != new_limit {
+            changes.push(format!(
+                "{}: {}/h (burst {}) -> {}/h (burst {})",
+                key, old_limit.per_hour, old_limit.burst, new_limit.per_hour, new_limit.burst
+            ));
+        }
+    }
+    let (old_bots, new_bots) = (&old.bot_protection, &new.bot_protection);
+    if (
+        old_bots.enabled,
+        old_bots.min_fill_seconds,
+        old_bots.max_age_seconds,
+    ) != (
+        new_bots.enabled,
+        new_bots.min_fill_seconds,
+        new_bots.max_age_seconds,
+    ) {
+        changes.push(format!(
+            "bot_protection: enabled {}, {}s..{}s -> enabled {}, {}s..{}s",
+            old_bots.enabled,
+            old_bots.min_fill_seconds,
+            old_bots.max_age_seconds,
+            new_bots.enabled,
+            new_bots.min_fill_seconds,
+            new_bots.max_age_seconds
+        ));
+    }
+    if old_bots.form_secret.expose_secret() != new_bots.form_secret.expose_secret() {
+        changes.push("bot_protection.form_secret: changed".to_string());
+    }
+    if old.email_policy != new.email_policy {
+        changes.push(format!(
+            "email_policy: {:?} -> {:?}",
+            old.email_policy, new.email_policy
+        ));
+    }
+    if old.cors != new.cors {
+        changes.push(format!("cors: {:?} -> {:?}", old.cors, new.cors));
+    }
+    if old.api.token.expose_secret() != new.api.token.expose_secret() {
+        changes.push("api.token: changed".to_string());
+    }
+    changes
+}
@@ -1,3 +1,4 @@
+pub mod api;
 pub mod health_check;
 pub mod subscriptions;
 
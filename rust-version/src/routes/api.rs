@@ -0,0 +1,86 @@
+//! src/routes/api.rs
+//! Versioned JSON API (`/api/v1/...`), authenticated with `ApiCaller` (scopes: `Scoped`).
+//! Admins are granted the scopes of their role (see `authentication::Role`).
+
+pub mod api_keys;
+pub mod email_domain_rules;
+pub mod subscribers;
+pub mod users;
+
+pub use api_keys::*;
+pub use email_domain_rules::*;
+pub use subscribers::*;
+pub use users::*;
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+
+use crate::email_policy::PolicyViolation;
+use crate::problem::Problem;
+
+/// Errors returned by the API handlers.
+///
+/// `code` is part of the API contract: clients branch on it, so existing codes must never change.
+/// (The `detail` is for humans and can be reworded at will.)
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    detail: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> Self {
+        // The details stay in our logs, the caller only learns that something went wrong.
+        tracing::error!("Failed to execute query: {:?}", e);
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Something went wrong on our side",
+        )
+    }
+}
+
+impl From<PolicyViolation> for ApiError {
+    fn from(violation: PolicyViolation) -> Self {
+        Self::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            violation.code(),
+            violation.to_string(),
+        )
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code, self.detail)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        Problem::new(self.status)
+            .with_type(&self.code.replace('_', "-"))
+            .with_detail(self.detail.clone())
+            .with("code", self.code)
+            .error_response()
+    }
+}
@@ -0,0 +1,260 @@
+//! src/routes/api/api_keys.rs
+//! `/api/v1/api-keys`: credentials of machine-to-machine callers (see `authentication`).
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, web};
+use chrono::{DateTime, SubsecRound, Utc};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::ApiError;
+use crate::authentication::{Scope, Scoped, generate_api_key, hash_api_key, scopes};
+use crate::problem::Problem;
+
+#[derive(serde::Serialize, utoipa::ToSchema, Debug)]
+pub struct ApiKey {
+    pub id: Uuid,
+    // The public part of the key, `z2p_<prefix>_...`
+    pub prefix: String,
+    pub owner: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// The response to a creation: the only time the key is ever shown.
+#[derive(serde::Serialize, utoipa::ToSchema, Debug)]
+pub struct CreatedApiKey {
+    // To send as `Authorization: Bearer <key>`. Not stored: it cannot be shown again.
+    pub key: String,
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CreateApiKeyRequest {
+    pub owner: String,
+    pub scopes: Vec<Scope>,
+    // Never expires when missing
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn to_scopes(scopes: Vec<String>) -> Vec<Scope> {
+    // A scope we no longer know grants nothing: it is not listed either
+    scopes
+        .into_iter()
+        .filter_map(|scope| Scope::try_from(scope).ok())
+        .collect()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/api-keys",
+    tag = "api keys",
+    security(("api_token" = ["api_keys:manage"]), ("admin_user" = ["api_keys:manage"])),
+    responses(
+        (status = 200, description = "Every key, revoked ones included, oldest first (without their secret)", body = [ApiKey]),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `api_keys:manage` scope (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Listing API keys", skip(db_conn, _caller))]
+pub async fn list_api_keys(
+    db_conn: web::Data<PgPool>,
+    _caller: Scoped<scopes::ApiKeysManage>,
+) -> Result<HttpResponse, ApiError> {
+    let keys: Vec<ApiKey> = sqlx::query!(
+        r#"
+        SELECT id, prefix, owner, scopes, created_at, last_used_at, expires_at, revoked_at
+        FROM api_keys
+        ORDER BY created_at, id
+        "#
+    )
+    .fetch_all(db_conn.get_ref())
+    .await?
+    .into_iter()
+    .map(|row| ApiKey {
+        id: row.id,
+        prefix: row.prefix,
+        owner: row.owner,
+        scopes: to_scopes(row.scopes),
+        created_at: row.created_at,
+        last_used_at: row.last_used_at,
+        expires_at: row.expires_at,
+        revoked_at: row.revoked_at,
+    })
+    .collect();
+
+    Ok(HttpResponse::Ok().json(keys))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/api-keys",
+    tag = "api keys",
+    security(("api_token" = ["api_keys:manage"]), ("admin_user" = ["api_keys:manage"])),
+    request_body = CreateApiKeyRequest,
+    responses(
+        (status = 201, description = "The created key, with its secret (shown this once)", body = CreatedApiKey),
+        (status = 422, description = "Invalid key (`invalid_owner`, `invalid_scopes`, `invalid_expiry`)", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `api_keys:manage` scope, or any of the requested scopes (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Creating an API key", skip(body, db_conn, caller), fields(owner = %body.owner))]
+pub async fn create_api_key(
+    body: web::Json<CreateApiKeyRequest>,
+    db_conn: web::Data<PgPool>,
+    caller: Scoped<scopes::ApiKeysManage>,
+) -> Result<HttpResponse, ApiError> {
+    let body = body.into_inner();
+    let owner = body.owner.trim().to_string();
+    if owner.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_owner",
+            "The owner cannot be empty",
+        ));
+    }
+    let mut scopes = vec![];
+    for scope in body.scopes {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    if scopes.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_scopes",
+            "A key needs at least one scope",
+        ));
+    }
+    // No escalation: a key only ever grants what its issuer holds
+    if let Some(scope) = scopes.iter().find(|scope| !caller.scopes.contains(scope)) {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "insufficient_scope",
+            format!(
+                "Cannot grant the `{}` scope without holding it",
+                scope.as_str()
+            ),
+        ));
+    }
+    let now = Utc::now();
+    if body.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_expiry",
+            "`expires_at` must be in the future",
+        ));
+    }
+
+    let (prefix, key) = generate_api_key();
+    let api_key = ApiKey {
+        id: Uuid::new_v4(),
+        prefix,
+        owner,
+        scopes,
+        // Postgres stores microseconds: truncating keeps the response in sync with the row
+        created_at: now.trunc_subsecs(6),
+        last_used_at: None,
+        expires_at: body
+            .expires_at
+            .map(|expires_at| expires_at.trunc_subsecs(6)),
+        revoked_at: None,
+    };
+    let scope_names: Vec<String> = api_key
+        .scopes
+        .iter()
+        .map(|scope| scope.as_str().to_string())
+        .collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_keys(id, prefix, key_hash, owner, scopes, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        api_key.id,
+        api_key.prefix,
+        hash_api_key(&key),
+        api_key.owner,
+        &scope_names,
+        api_key.created_at,
+        api_key.expires_at,
+    )
+    .execute(db_conn.get_ref())
+    .await?;
+
+    Ok(HttpResponse::Created().json(CreatedApiKey {
+        key: key.expose_secret().clone(),
+        api_key,
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/api-keys/{id}",
+    tag = "api keys",
+    security(("api_token" = ["api_keys:manage"]), ("admin_user" = ["api_keys:manage"])),
+    params(("id" = Uuid, Path, description = "API key id")),
+    responses(
+        (status = 204, description = "Revoked: the key is refused from now on"),
+        (status = 404, description = "Unknown or already revoked key (`api_key_not_found`)", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `api_keys:manage` scope, or any of the scopes of the key (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Revoking an API key", skip(db_conn, caller))]
+pub async fn revoke_api_key(
+    id: web::Path<Uuid>,
+    db_conn: web::Data<PgPool>,
+    caller: Scoped<scopes::ApiKeysManage>,
+) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    let not_found = || {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            "api_key_not_found",
+            format!("No active API key with id {}", id),
+        )
+    };
+    let key_scopes = sqlx::query_scalar!(
+        "SELECT scopes FROM api_keys WHERE id = $1 AND revoked_at IS NULL",
+        id
+    )
+    .fetch_optional(db_conn.get_ref())
+    .await?
+    .ok_or_else(not_found)?;
+    // Same rule as for creation: no revoking a key broader than the caller
+    // (e.g. a narrow key taking down the CMS key)
+    if let Some(scope) = to_scopes(key_scopes)
+        .iter()
+        .find(|scope| !caller.scopes.contains(scope))
+    {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "insufficient_scope",
+            format!(
+                "Cannot revoke a key granting the `{}` scope without holding it",
+                scope.as_str()
+            ),
+        ));
+    }
+    let revoked = sqlx::query!(
+        "UPDATE api_keys SET revoked_at = $2 WHERE id = $1 AND revoked_at IS NULL",
+        id,
+        Utc::now()
+    )
+    .execute(db_conn.get_ref())
+    .await?
+    .rows_affected();
+    // Revoked concurrently, in between
+    if revoked == 0 {
+        return Err(not_found());
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,274 @@
+//! src/routes/api/email_domain_rules.rs
+//! `/api/v1/email-domain-rules`: the operator's exceptions to the email domain policy
+//! (see `email_policy`), effective immediately.
+
+use actix_web::http::StatusCode;
+use actix_web::http::header::LOCATION;
+use actix_web::{HttpResponse, web};
+use chrono::{DateTime, SubsecRound, Utc};
+use sqlx::PgPool;
+
+use super::ApiError;
+use crate::authentication::{Scoped, scopes};
+use crate::email_policy::{RuleKind, normalize_domain};
+use crate::problem::Problem;
+
+#[derive(serde::Serialize, utoipa::ToSchema, Debug)]
+pub struct EmailDomainRule {
+    // Lowercase, the rule covers its subdomains too
+    pub domain: String,
+    pub kind: RuleKind,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CreateEmailDomainRuleRequest {
+    pub domain: String,
+    pub kind: RuleKind,
+    // Why the rule exists, for the next operator
+    pub note: Option<String>,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateEmailDomainRuleRequest {
+    pub kind: RuleKind,
+    pub note: Option<String>,
+}
+
+fn to_kind(kind: String) -> Result<RuleKind, ApiError> {
+    RuleKind::try_from(kind).map_err(|e| {
+        tracing::error!("Corrupted email domain rule: {}", e);
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Something went wrong on our side",
+        )
+    })
+}
+
+fn validate_domain(domain: &str) -> Result<String, ApiError> {
+    let domain = normalize_domain(domain);
+    if domain.is_empty() || domain.contains(['@', '/']) || domain.contains(char::is_whitespace) {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_domain",
+            "Expected a domain name, e.g. `example.com`",
+        ));
+    }
+    Ok(domain)
+}
+
+fn not_found(domain: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        "email_domain_rule_not_found",
+        format!("No rule for {}", domain),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/email-domain-rules",
+    tag = "email domain rules",
+    security(("api_token" = ["email_domain_rules:manage"]), ("admin_user" = ["email_domain_rules:manage"])),
+    responses(
+        (status = 200, description = "Every rule, by domain", body = [EmailDomainRule]),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `email_domain_rules:manage` scope (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Listing email domain rules", skip(db_conn, _caller))]
+pub async fn list_email_domain_rules(
+    db_conn: web::Data<PgPool>,
+    _caller: Scoped<scopes::EmailDomainRulesManage>,
+) -> Result<HttpResponse, ApiError> {
+    // A handful of rows: no pagination
+    let rules = sqlx::query!(
+        "SELECT domain, kind, note, created_at FROM email_domain_rules ORDER BY domain"
+    )
+    .fetch_all(db_conn.get_ref())
+    .await?
+    .into_iter()
+    .map(|row| {
+        Ok(EmailDomainRule {
+            domain: row.domain,
+            kind: to_kind(row.kind)?,
+            note: row.note,
+            created_at: row.created_at,
+        })
+    })
+    .collect::<Result<Vec<_>, ApiError>>()?;
+
+    Ok(HttpResponse::Ok().json(rules))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/email-domain-rules/{domain}",
+    tag = "email domain rules",
+    security(("api_token" = ["email_domain_rules:manage"]), ("admin_user" = ["email_domain_rules:manage"])),
+    params(("domain" = String, Path, description = "Domain of the rule")),
+    responses(
+        (status = 200, description = "The rule", body = EmailDomainRule),
+        (status = 404, description = "No rule for this domain (`email_domain_rule_not_found`)", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `email_domain_rules:manage` scope (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Fetching an email domain rule", skip(db_conn, _caller))]
+pub async fn get_email_domain_rule(
+    domain: web::Path<String>,
+    db_conn: web::Data<PgPool>,
+    _caller: Scoped<scopes::EmailDomainRulesManage>,
+) -> Result<HttpResponse, ApiError> {
+    let domain = normalize_domain(&domain);
+    let row = sqlx::query!(
+        "SELECT domain, kind, note, created_at FROM email_domain_rules WHERE domain = $1",
+        domain
+    )
+    .fetch_optional(db_conn.get_ref())
+    .await?
+    .ok_or_else(|| not_found(&domain))?;
+
+    Ok(HttpResponse::Ok().json(EmailDomainRule {
+        domain: row.domain,
+        kind: to_kind(row.kind)?,
+        note: row.note,
+        created_at: row.created_at,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/email-domain-rules",
+    tag = "email domain rules",
+    security(("api_token" = ["email_domain_rules:manage"]), ("admin_user" = ["email_domain_rules:manage"])),
+    request_body = CreateEmailDomainRuleRequest,
+    responses(
+        (status = 201, description = "The created rule", body = EmailDomainRule),
+        (status = 409, description = "This domain already has a rule (`email_domain_rule_exists`)", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid domain (`invalid_domain`)", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `email_domain_rules:manage` scope (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Creating an email domain rule", skip(db_conn, _caller))]
+pub async fn create_email_domain_rule(
+    body: web::Json<CreateEmailDomainRuleRequest>,
+    db_conn: web::Data<PgPool>,
+    _caller: Scoped<scopes::EmailDomainRulesManage>,
+) -> Result<HttpResponse, ApiError> {
+    let body = body.into_inner();
+    let rule = EmailDomainRule {
+        domain: validate_domain(&body.domain)?,
+        kind: body.kind,
+        note: body.note,
+        // Postgres stores microseconds: truncating keeps the response in sync with the row
+        created_at: Utc::now().trunc_subsecs(6),
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO email_domain_rules(domain, kind, note, created_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        rule.domain,
+        rule.kind.as_str(),
+        rule.note,
+        rule.created_at,
+    )
+    .execute(db_conn.get_ref())
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => ApiError::new(
+            StatusCode::CONFLICT,
+            "email_domain_rule_exists",
+            format!("{} already has a rule", rule.domain),
+        ),
+        e => e.into(),
+    })?;
+
+    Ok(HttpResponse::Created()
+        .insert_header((
+            LOCATION,
+            format!("/api/v1/email-domain-rules/{}", rule.domain),
+        ))
+        .json(rule))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/email-domain-rules/{domain}",
+    tag = "email domain rules",
+    security(("api_token" = ["email_domain_rules:manage"]), ("admin_user" = ["email_domain_rules:manage"])),
+    params(("domain" = String, Path, description = "Domain of the rule")),
+    request_body = UpdateEmailDomainRuleRequest,
+    responses(
+        (status = 200, description = "The updated rule", body = EmailDomainRule),
+        (status = 404, description = "No rule for this domain (`email_domain_rule_not_found`)", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `email_domain_rules:manage` scope (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Updating an email domain rule", skip(db_conn, _caller))]
+pub async fn update_email_domain_rule(
+    domain: web::Path<String>,
+    body: web::Json<UpdateEmailDomainRuleRequest>,
+    db_conn: web::Data<PgPool>,
+    _caller: Scoped<scopes::EmailDomainRulesManage>,
+) -> Result<HttpResponse, ApiError> {
+    let domain = normalize_domain(&domain);
+    let row = sqlx::query!(
+        r#"
+        UPDATE email_domain_rules SET kind = $2, note = $3
+        WHERE domain = $1
+        RETURNING domain, kind, note, created_at
+        "#,
+        domain,
+        body.kind.as_str(),
+        body.note
+    )
+    .fetch_optional(db_conn.get_ref())
+    .await?
+    .ok_or_else(|| not_found(&domain))?;
+
+    Ok(HttpResponse::Ok().json(EmailDomainRule {
+        domain: row.domain,
+        kind: to_kind(row.kind)?,
+        note: row.note,
+        created_at: row.created_at,
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/email-domain-rules/{domain}",
+    tag = "email domain rules",
+    security(("api_token" = ["email_domain_rules:manage"]), ("admin_user" = ["email_domain_rules:manage"])),
+    params(("domain" = String, Path, description = "Domain of the rule")),
+    responses(
+        (status = 204, description = "Deleted: the domain falls back to the default policy"),
+        (status = 404, description = "No rule for this domain (`email_domain_rule_not_found`)", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `email_domain_rules:manage` scope (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Deleting an email domain rule", skip(db_conn, _caller))]
+pub async fn delete_email_domain_rule(
+    domain: web::Path<String>,
+    db_conn: web::Data<PgPool>,
+    _caller: Scoped<scopes::EmailDomainRulesManage>,
+) -> Result<HttpResponse, ApiError> {
+    let domain = normalize_domain(&domain);
+    let deleted = sqlx::query!("DELETE FROM email_domain_rules WHERE domain = $1", domain)
+        .execute(db_conn.get_ref())
+        .await?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(not_found(&domain));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
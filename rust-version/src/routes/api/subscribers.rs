@@ -0,0 +1,433 @@
+//! src/routes/api/subscribers.rs
+//! `/api/v1/subscribers`: programmatic management of the `subscriptions` table.
+
+use actix_web::http::StatusCode;
+use actix_web::http::header::LOCATION;
+use actix_web::{HttpResponse, web};
+use chrono::{DateTime, SubsecRound, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::ApiError;
+use crate::audit::record_audit_event;
+use crate::authentication::{Scoped, scopes};
+use crate::domain::{SubscriberEmail, SubscriptionStatus};
+use crate::email_policy::find_violation;
+use crate::problem::Problem;
+use crate::reload::LiveSettings;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(serde::Serialize, utoipa::ToSchema, Debug)]
+pub struct Subscriber {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: SubscriptionStatus,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize, utoipa::ToSchema, Debug)]
+pub struct SubscriberPage {
+    pub data: Vec<Subscriber>,
+    // Pass it back as `cursor` to get the next page. `null` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams, Debug)]
+#[serde(deny_unknown_fields)]
+#[into_params(parameter_in = Query)]
+pub struct ListSubscribersQuery {
+    pub status: Option<SubscriptionStatus>,
+    // RFC 3339 timestamps, `subscribed_after` is inclusive, `subscribed_before` exclusive
+    pub subscribed_after: Option<DateTime<Utc>>,
+    pub subscribed_before: Option<DateTime<Utc>>,
+    // Case-insensitive
+    pub email_prefix: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CreateSubscriberRequest {
+    pub email: String,
+    pub name: String,
+    // Skips double opt-in: the subscriber is created as `confirmed`.
+    // Only for consent collected elsewhere, hence the mandatory `audit_reason`.
+    #[serde(default)]
+    pub already_consented: bool,
+    pub audit_reason: Option<String>,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateSubscriberRequest {
+    pub name: String,
+}
+
+// Pagination is keyed on (subscribed_at, id): stable even when rows are inserted meanwhile,
+// unlike OFFSET-based pagination.
+// The cursor is opaque to clients: its format can change at any time.
+struct Cursor {
+    subscribed_at: DateTime<Utc>,
+    id: Uuid,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        format!("{}_{}", self.subscribed_at.timestamp_micros(), self.id)
+    }
+
+    fn decode(cursor: &str) -> Result<Self, ApiError> {
+        let invalid = || ApiError::new(StatusCode::BAD_REQUEST, "invalid_cursor", "Invalid cursor");
+        let (micros, id) = cursor.split_once('_').ok_or_else(invalid)?;
+        let micros = micros.parse::<i64>().map_err(|_| invalid())?;
+        Ok(Self {
+            subscribed_at: DateTime::from_timestamp_micros(micros).ok_or_else(invalid)?,
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+fn to_status(status: String) -> Result<SubscriptionStatus, ApiError> {
+    SubscriptionStatus::try_from(status).map_err(|e| {
+        tracing::error!("Corrupted subscription status: {}", e);
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Something went wrong on our side",
+        )
+    })
+}
+
+fn validate_name(name: &str) -> Result<String, ApiError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_name",
+            "The name cannot be empty",
+        ));
+    }
+    Ok(name.to_string())
+}
+
+fn not_found(id: Uuid) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        "subscriber_not_found",
+        format!("No subscriber with id {}", id),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers",
+    tag = "subscribers",
+    security(("api_token" = ["subscribers:read"]), ("admin_user" = ["subscribers:read"])),
+    params(ListSubscribersQuery),
+    responses(
+        (status = 200, description = "A page of subscribers, oldest first", body = SubscriberPage),
+        (status = 400, description = "Invalid filters (`invalid_cursor`, `invalid_limit`)", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `subscribers:read` scope (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Listing subscribers", skip(db_conn, _caller))]
+pub async fn list_subscribers(
+    query: web::Query<ListSubscribersQuery>,
+    db_conn: web::Data<PgPool>,
+    _caller: Scoped<scopes::SubscribersRead>,
+) -> Result<HttpResponse, ApiError> {
+    let query = query.into_inner();
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_limit",
+            format!("`limit` must be between 1 and {}", MAX_PAGE_SIZE),
+        ));
+    }
+    let cursor = query.cursor.as_deref().map(Cursor::decode).transpose()?;
+    // `%` and `_` are wildcards for LIKE: a prefix containing them must match them literally
+    let email_prefix = query.email_prefix.map(|prefix| {
+        prefix
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    });
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR status = $1)
+          AND ($2::timestamptz IS NULL OR subscribed_at >= $2)
+          AND ($3::timestamptz IS NULL OR subscribed_at < $3)
+          AND ($4::text IS NULL OR lower(email) LIKE lower($4) || '%')
+          AND ($5::timestamptz IS NULL OR (subscribed_at, id) > ($5, $6::uuid))
+        ORDER BY subscribed_at, id
+        LIMIT $7
+        "#,
+        query.status.map(|s| s.as_str()),
+        query.subscribed_after,
+        query.subscribed_before,
+        email_prefix,
+        cursor.as_ref().map(|c| c.subscribed_at),
+        cursor.as_ref().map(|c| c.id),
+        // One extra row tells us whether there is a next page
+        limit + 1,
+    )
+    .fetch_all(db_conn.get_ref())
+    .await?;
+
+    let has_next_page = rows.len() as i64 > limit;
+    let data = rows
+        .into_iter()
+        .take(limit as usize)
+        .map(|row| {
+            Ok(Subscriber {
+                id: row.id,
+                email: row.email,
+                name: row.name,
+                status: to_status(row.status)?,
+                subscribed_at: row.subscribed_at,
+            })
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?;
+    let next_cursor = data.last().filter(|_| has_next_page).map(|last| {
+        Cursor {
+            subscribed_at: last.subscribed_at,
+            id: last.id,
+        }
+        .encode()
+    });
+
+    Ok(HttpResponse::Ok().json(SubscriberPage { data, next_cursor }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers/{id}",
+    tag = "subscribers",
+    security(("api_token" = ["subscribers:read"]), ("admin_user" = ["subscribers:read"])),
+    params(("id" = Uuid, Path, description = "Subscriber id")),
+    responses(
+        (status = 200, description = "The subscriber", body = Subscriber),
+        (status = 404, description = "Unknown subscriber (`subscriber_not_found`)", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `subscribers:read` scope (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Fetching a subscriber", skip(db_conn, _caller))]
+pub async fn get_subscriber(
+    id: web::Path<Uuid>,
+    db_conn: web::Data<PgPool>,
+    _caller: Scoped<scopes::SubscribersRead>,
+) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    let row = sqlx::query!(
+        "SELECT id, email, name, status, subscribed_at FROM subscriptions WHERE id = $1",
+        id
+    )
+    .fetch_optional(db_conn.get_ref())
+    .await?
+    .ok_or_else(|| not_found(id))?;
+
+    Ok(HttpResponse::Ok().json(Subscriber {
+        id: row.id,
+        email: row.email,
+        name: row.name,
+        status: to_status(row.status)?,
+        subscribed_at: row.subscribed_at,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscribers",
+    tag = "subscribers",
+    security(("api_token" = ["subscribers:write"]), ("admin_user" = ["subscribers:write"])),
+    request_body = CreateSubscriberRequest,
+    responses(
+        (status = 201, description = "The created subscriber", body = Subscriber),
+        (status = 409, description = "Email already subscribed (`email_already_subscribed`)", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid subscriber (`invalid_email`, `invalid_name`, `audit_reason_required`, or refused by the email policy: `email_role_address`, `email_domain_denied`, `email_domain_disposable`)", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `subscribers:write` scope (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(
+    name = "Creating a subscriber through the API",
+    skip(body, db_conn, live_settings, caller),
+    fields(subscriber_email = %body.email, already_consented = body.already_consented)
+)]
+pub async fn create_subscriber(
+    body: web::Json<CreateSubscriberRequest>,
+    db_conn: web::Data<PgPool>,
+    live_settings: web::Data<LiveSettings>,
+    caller: Scoped<scopes::SubscribersWrite>,
+) -> Result<HttpResponse, ApiError> {
+    let body = body.into_inner();
+    let email = SubscriberEmail::parse(&body.email)
+        .map_err(|e| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "invalid_email", e))?;
+    let name = validate_name(&body.name)?;
+    // The same policy as the public form: consent collected elsewhere is no exception
+    let email_policy = &live_settings.current().email_policy;
+    if let Some(violation) = find_violation(&email, email_policy, &db_conn).await? {
+        return Err(violation.into());
+    }
+    let audit_reason = body
+        .audit_reason
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty());
+    let status = match (body.already_consented, &audit_reason) {
+        (false, _) => SubscriptionStatus::PendingConfirmation,
+        (true, Some(_)) => SubscriptionStatus::Confirmed,
+        (true, None) => {
+            return Err(ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "audit_reason_required",
+                "`audit_reason` is mandatory when `already_consented` is true",
+            ));
+        }
+    };
+
+    let subscriber = Subscriber {
+        id: Uuid::new_v4(),
+        email: email.to_string(),
+        name,
+        status,
+        // Postgres stores microseconds: truncating keeps the response in sync with the row
+        subscribed_at: Utc::now().trunc_subsecs(6),
+    };
+
+    // Both writes, or none: a confirmed subscriber without its audit trail is not acceptable.
+    let mut transaction = db_conn.begin().await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions(id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        subscriber.id,
+        subscriber.email,
+        subscriber.name,
+        subscriber.subscribed_at,
+        subscriber.status.as_str(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => ApiError::new(
+            StatusCode::CONFLICT,
+            "email_already_subscribed",
+            // Whatever its case: `subscriptions_email_lower_key`
+            "This email is already subscribed",
+        ),
+        e => e.into(),
+    })?;
+    if let Some(reason) = audit_reason {
+        record_audit_event(
+            &mut *transaction,
+            &caller.name,
+            "subscriber.created_without_opt_in",
+            &format!("subscriber:{}", subscriber.id),
+            Some(reason),
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Created()
+        .insert_header((LOCATION, format!("/api/v1/subscribers/{}", subscriber.id)))
+        .json(subscriber))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/subscribers/{id}",
+    tag = "subscribers",
+    security(("api_token" = ["subscribers:write"]), ("admin_user" = ["subscribers:write"])),
+    params(("id" = Uuid, Path, description = "Subscriber id")),
+    request_body = UpdateSubscriberRequest,
+    responses(
+        (status = 200, description = "The updated subscriber", body = Subscriber),
+        (status = 404, description = "Unknown subscriber (`subscriber_not_found`)", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid name (`invalid_name`)", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `subscribers:write` scope (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Updating a subscriber", skip(body, db_conn, _caller))]
+pub async fn update_subscriber(
+    id: web::Path<Uuid>,
+    body: web::Json<UpdateSubscriberRequest>,
+    db_conn: web::Data<PgPool>,
+    _caller: Scoped<scopes::SubscribersWrite>,
+) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    let name = validate_name(&body.name)?;
+    let row = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET name = $2
+        WHERE id = $1
+        RETURNING id, email, name, status, subscribed_at
+        "#,
+        id,
+        name
+    )
+    .fetch_optional(db_conn.get_ref())
+    .await?
+    .ok_or_else(|| not_found(id))?;
+
+    Ok(HttpResponse::Ok().json(Subscriber {
+        id: row.id,
+        email: row.email,
+        name: row.name,
+        status: to_status(row.status)?,
+        subscribed_at: row.subscribed_at,
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/subscribers/{id}",
+    tag = "subscribers",
+    security(("api_token" = ["subscribers:write"]), ("admin_user" = ["subscribers:write"])),
+    params(("id" = Uuid, Path, description = "Subscriber id")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, description = "Unknown subscriber (`subscriber_not_found`)", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `subscribers:write` scope (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Deleting a subscriber", skip(db_conn, caller))]
+pub async fn delete_subscriber(
+    id: web::Path<Uuid>,
+    db_conn: web::Data<PgPool>,
+    caller: Scoped<scopes::SubscribersWrite>,
+) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    let mut transaction = db_conn.begin().await?;
+    let deleted = sqlx::query!("DELETE FROM subscriptions WHERE id = $1", id)
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(not_found(id));
+    }
+    record_audit_event(
+        &mut *transaction,
+        &caller.name,
+        "subscriber.deleted",
+        &format!("subscriber:{}", id),
+        None,
+    )
+    .await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
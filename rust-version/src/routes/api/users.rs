@@ -0,0 +1,137 @@
+//! src/routes/api/users.rs
+//! `/api/v1/users`: the roles of the admins (created with `zero2prod create-admin`).
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, web};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use super::ApiError;
+use crate::audit::record_audit_event;
+use crate::authentication::{Role, Scoped, scopes};
+use crate::problem::Problem;
+
+#[derive(serde::Serialize, utoipa::ToSchema, Debug)]
+pub struct User {
+    pub username: String,
+    pub role: Role,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateRoleRequest {
+    pub role: Role,
+}
+
+fn to_role(role: String) -> Result<Role, ApiError> {
+    Role::try_from(role).map_err(|e| sqlx::Error::Decode(e.into()).into())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users",
+    tag = "users",
+    security(("api_token" = ["users:manage"]), ("admin_user" = ["users:manage"])),
+    responses(
+        (status = 200, description = "Every admin, oldest first", body = [User]),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `users:manage` scope (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Listing users", skip(db_conn, _caller))]
+pub async fn list_users(
+    db_conn: web::Data<PgPool>,
+    _caller: Scoped<scopes::UsersManage>,
+) -> Result<HttpResponse, ApiError> {
+    let rows = sqlx::query!("SELECT username, role, created_at FROM users ORDER BY created_at")
+        .fetch_all(db_conn.get_ref())
+        .await?;
+    let users = rows
+        .into_iter()
+        .map(|row| {
+            Ok(User {
+                username: row.username,
+                role: to_role(row.role)?,
+                created_at: row.created_at,
+            })
+        })
+        .collect::<Result<Vec<_>, ApiError>>()?;
+
+    Ok(HttpResponse::Ok().json(users))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/users/{username}/role",
+    tag = "users",
+    security(("api_token" = ["users:manage"]), ("admin_user" = ["users:manage"])),
+    params(("username" = String, Path, description = "Username")),
+    request_body = UpdateRoleRequest,
+    responses(
+        (status = 200, description = "The user, with its new role", body = User),
+        (status = 404, description = "Unknown user (`user_not_found`)", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "Would leave no owner at all (`last_owner`)", body = Problem, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid credentials (`unauthorized`)", body = Problem, content_type = "application/problem+json"),
+        (status = 403, description = "Missing the `users:manage` scope (`insufficient_scope`)", body = Problem, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Changing the role of a user", skip(body, db_conn, caller))]
+pub async fn update_user_role(
+    username: web::Path<String>,
+    body: web::Json<UpdateRoleRequest>,
+    db_conn: web::Data<PgPool>,
+    caller: Scoped<scopes::UsersManage>,
+) -> Result<HttpResponse, ApiError> {
+    let username = username.into_inner();
+    let role = body.into_inner().role;
+    let mut transaction = db_conn.begin().await?;
+    // Every owner row is locked: two owners demoting each other at once cannot both succeed
+    let owners = sqlx::query_scalar!("SELECT username FROM users WHERE role = 'owner' FOR UPDATE")
+        .fetch_all(&mut *transaction)
+        .await?;
+    let user = sqlx::query!(
+        "SELECT role, created_at FROM users WHERE username = $1 FOR UPDATE",
+        username
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            "user_not_found",
+            format!("No user named `{}`", username),
+        )
+    })?;
+    let previous = to_role(user.role)?;
+    if previous == Role::Owner && role != Role::Owner && owners.len() == 1 {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "last_owner",
+            "The last owner cannot be demoted: promote another user first",
+        ));
+    }
+
+    sqlx::query!(
+        "UPDATE users SET role = $2 WHERE username = $1",
+        username,
+        role.as_str()
+    )
+    .execute(&mut *transaction)
+    .await?;
+    record_audit_event(
+        &mut *transaction,
+        &caller.name,
+        "user.role_changed",
+        &format!("user:{}", username),
+        Some(format!("{} -> {}", previous.as_str(), role.as_str())),
+    )
+    .await?;
+    transaction.commit().await?;
+
+    Ok(HttpResponse::Ok().json(User {
+        username,
+        role,
+        created_at: user.created_at,
+    }))
+}
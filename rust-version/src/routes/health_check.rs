@@ -4,6 +4,12 @@
 // Example: String::from("text") vs my_string.len()
 use actix_web::{HttpResponse, Responder};
 
+#[utoipa::path(
+    get,
+    path = "/health_check",
+    tag = "public",
+    responses((status = 200, description = "The service is up (empty body)"))
+)]
 pub async fn health_check() -> impl Responder {
     // impl Responder = "returns some concrete type that implements the Responder trait"
     // The caller doesn't know the exact type, just that it satisfies the Responder contract
@@ -2,10 +2,23 @@
 // :: is the path/namespace separator (for modules, types, static functions)
 // . is for method calls on instances
 // Example: String::from("text") vs my_string.len()
+use actix_web::http::StatusCode;
+use actix_web::http::header::CACHE_CONTROL;
 use actix_web::{HttpResponse, web};
 use chrono::Utc;
 use sqlx::PgPool;
 use uuid::Uuid;
+
+use crate::bot_protection::{
+    BotSignal, HONEYPOT_FIELD, check_submission, issue_form_token, spend_form_token,
+};
+use crate::domain::{SubscriberEmail, SubscriptionStatus};
+use crate::email_policy::find_violation;
+use crate::extractors::JsonOrForm;
+use crate::metrics::Metrics;
+use crate::problem::Problem;
+use crate::rate_limit::RateLimiter;
+use crate::reload::LiveSettings;
 /*
 * EXTRACTORS - Type-safe request parsing (like http4s EntityDecoder)
 *
@@ -33,18 +46,69 @@ use uuid::Uuid;
 // SCALA EQUIVALENT: case class FormData(email: String, name: String) derives Decoder
 // Both Rust #[derive(...)] and Scala 3 derives use compile-time code generation
 // to auto-implement typeclass instances (Deserialize in Rust, Decoder in Scala)
-#[derive(serde::Deserialize)]
+// NOTE: the same `Deserialize` impl serves both JSON and url-encoded bodies (see `JsonOrForm`)
+// `ToSchema` describes the type in the OpenAPI document (see `openapi.rs`)
+#[derive(serde::Deserialize, utoipa::ToSchema)]
 pub struct FormData {
     email: String,
     name: String,
+    // Bot checks (see `bot_protection`): the honeypot, to be left EMPTY...
+    website: Option<String>,
+    // ... and the token of `GET /subscription/form-token`
+    form_token: Option<String>,
+}
+
+/// The fields the signup form must carry when bot protection is on.
+#[derive(serde::Serialize, utoipa::ToSchema)]
+pub struct FormToken {
+    // To be sent back as is in `form_token`
+    form_token: String,
+    // The name of the hidden field to include, left empty
+    honeypot_field: &'static str,
+}
+
+#[utoipa::path(
+    get,
+    path = "/subscription/form-token",
+    tag = "public",
+    responses(
+        (status = 200, description = "A fresh form token, to fetch when the signup form is displayed", body = FormToken)
+    )
+)]
+pub async fn form_token(live_settings: web::Data<LiveSettings>) -> HttpResponse {
+    let settings = live_settings.current();
+    HttpResponse::Ok()
+        // One token per form display: no cache along the way should hand out the same one
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .json(FormToken {
+            form_token: issue_form_token(&settings.bot_protection.form_secret, Utc::now()),
+            honeypot_field: HONEYPOT_FIELD,
+        })
 }
 
+#[utoipa::path(
+    post,
+    path = "/subscription",
+    tag = "public",
+    request_body(content(
+        (FormData = "application/x-www-form-urlencoded"),
+        (FormData = "application/json")
+    )),
+    responses(
+        (status = 200, description = "Subscribed, pending confirmation (empty body). Also the answer to an address already subscribed (whatever its case) and to detected bots, which are silently dropped"),
+        (status = 400, description = "Malformed body", body = Problem, content_type = "application/problem+json"),
+        (status = 415, description = "Unsupported content type", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid address (`code`: `invalid_email`) or refused by the email policy (`email_role_address`, `email_domain_denied`, `email_domain_disposable`)", body = Problem, content_type = "application/problem+json"),
+        (status = 429, description = "Too many attempts from this IP or for this email (see `Retry-After`)", body = Problem, content_type = "application/problem+json"),
+        (status = 500, description = "Failed to save the subscriber", body = Problem, content_type = "application/problem+json")
+    )
+)]
 // NOTE: thanks to TRACING’s log feature flag,
 // every time an event or a span are created using tracing’s macros
 // a corresponding log event is emitted, allowing loggers to pick up on it
 #[tracing::instrument(
     name="Adding a new subscriber", // default: func name, i.e subscribe
-    skip(_form, _db_conn),
+    skip(_form, _db_conn, live_settings, metrics, rate_limiter),
     fields(
         // CLAUDE: please remind me about this % syntax...
         // unique id to CORRELATE all logs related to the same request.
@@ -54,51 +118,116 @@ pub struct FormData {
     )
 )]
 pub async fn subscribe(
-    // web::Form<FormData> implements FromRequest trait
+    // JsonOrForm<FormData> implements FromRequest trait
     // When actix-web sees this parameter:
     //   1. It calls FromRequest::from_request()
-    //   2. That uses serde::Deserialize to parse form data
+    //   2. That picks JSON or url-encoded decoding based on the Content-Type header
+    //      (anything else → 415 Unsupported Media Type)
     //   3. Success → handler runs with parsed data
-    //   4. Failure → automatic 400 Bad Request (handler never runs)
+    //   4. Failure → 400 Bad Request naming the faulty field (handler never runs)
     //
     // SCALA: This is like req.as[FormData] using EntityDecoder + Decoder typeclasses
-    _form: web::Form<FormData>,
+    _form: JsonOrForm<FormData>,
     // Retrieving a connection from the application state!
     // by getting our hands on an Arc<PgPool> in the request handler, using the web::Data extractor:
     _db_conn: web::Data<PgPool>,
+    live_settings: web::Data<LiveSettings>,
+    metrics: web::Data<Metrics>,
+    // Also where the form tokens already used are remembered (see `bot_protection`)
+    rate_limiter: web::Data<RateLimiter>,
     // The key difference:
     //   RUST: Extraction happens as parameter (web::Form<FormData>)
     //         Type-level composition: FromRequest trait + serde Deserialize
     //   SCALA: Extraction happens explicitly via .as[FormData]
     //          Type-level composition: EntityDecoder[IO, FormData] + circe Decoder
-) -> HttpResponse {
+) -> Result<HttpResponse, Problem> {
     // NOTE: We only return 200 OK here, but the endpoint automatically returns
     // 400 Bad Request when form data is invalid/missing.
-    // This happens because web::Form<FormData> extraction fails before this handler runs,
+    // This happens because JsonOrForm<FormData> extraction fails before this handler runs,
     // and actix-web converts the extraction error into a 400 response automatically.
     //
     // SCALA: Same behavior - if req.as[FormData] fails to decode, http4s middleware
     //        automatically returns 400 Bad Request via DecodeFailure handling
-    match insert_subscriber(&_form, &_db_conn).await {
-        Ok(_) => HttpResponse::Ok().finish(),
-        Err(_) => HttpResponse::InternalServerError().finish(), // CLAUDE: what are those .finish() ???
+    // Bots get the very same answer as everyone else: nothing to learn, nothing to adapt to
+    let settings = live_settings.current();
+    let drop_bot = |signal: BotSignal| {
+        tracing::warn!(reason = signal.as_str(), "Bot submission dropped");
+        metrics.record_bot_rejection(signal);
+        HttpResponse::Ok().finish()
+    };
+    let form_token = match check_submission(
+        &settings.bot_protection,
+        _form.website.as_deref(),
+        _form.form_token.as_deref(),
+        Utc::now(),
+    ) {
+        Ok(form_token) => form_token,
+        Err(signal) => return Ok(drop_bot(signal)),
+    };
+    // Stored (and compared) in its canonical form only
+    let email = SubscriberEmail::parse(&_form.email).map_err(|e| {
+        Problem::new(StatusCode::UNPROCESSABLE_ENTITY)
+            .with_type("invalid-email")
+            .with_title("Invalid email address")
+            .with_detail(e)
+            .with("code", "invalid_email")
+    })?;
+    // Unlike bots, people typing a refused address deserve to know why
+    let violation = find_violation(&email, &settings.email_policy, &_db_conn)
+        .await
+        .map_err(|_| {
+            Problem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .with_detail("Failed to save the subscriber")
+        })?;
+    if let Some(violation) = violation {
+        return Err(Problem::new(StatusCode::UNPROCESSABLE_ENTITY)
+            .with_type("email-not-allowed")
+            .with_title("Email address not allowed")
+            .with_detail(violation.to_string())
+            .with("code", violation.code()));
+    }
+    // Only now: an address to correct is resubmitted with the same token
+    if let Some(form_token) = form_token
+        && let Err(signal) = spend_form_token(&rate_limiter, &form_token).await
+    {
+        return Ok(drop_bot(signal));
+    }
+    // Errors are returned as `Problem`s: rendered as `application/problem+json` bodies.
+    match insert_subscriber(&email, &_form.name, &_db_conn).await {
+        Ok(()) => {}
+        // Already subscribed, whatever the case (`subscriptions_email_lower_key`): idempotent.
+        // A 409 would tell anyone whether an address is on our list.
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            tracing::info!("Already subscribed: nothing to do");
+        }
+        Err(_) => {
+            return Err(Problem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .with_detail("Failed to save the subscriber"));
+        }
     }
+    Ok(HttpResponse::Ok().finish()) // .finish(): build the response with an empty body
 }
 
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(_form, _db_conn)
+    skip(email, name, _db_conn)
 )]
-pub async fn insert_subscriber(_form: &FormData, _db_conn: &PgPool) -> Result<(), sqlx::Error> {
+pub async fn insert_subscriber(
+    email: &SubscriberEmail,
+    name: &str,
+    _db_conn: &PgPool,
+) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"
-        INSERT INTO subscriptions(id, email, name, subscribed_at)
-        VALUES ($1, $2, $3, $4)
+        INSERT INTO subscriptions(id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, $5)
         "#,
         Uuid::new_v4(),
-        _form.email,
-        _form.name,
-        Utc::now()
+        email.as_ref(),
+        name,
+        Utc::now(),
+        // Double opt-in: the subscriber has yet to confirm they own the address
+        SubscriptionStatus::PendingConfirmation.as_str()
     )
     // .execute(_db_conn)
     // CLAUDE: why don't we get a ref in this case ????
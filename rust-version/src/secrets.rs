@@ -0,0 +1,182 @@
+//! src/secrets.rs
+//! Secrets kept out of the YAML files (and therefore out of the Docker image).
+//!
+//! Any secret setting can be replaced by a REFERENCE to where the secret lives:
+//!
+//! ```yaml
+//! database:
+//!   user:
+//!     password_file: /run/secrets/db_password # read from a file (Docker/Kubernetes secrets)
+//! api:
+//!   token_env: NEWSLETTER_API_TOKEN           # read from another environment variable
+//! ```
+//!
+//! References are resolved while the configuration is loaded, before deserialization:
+//! `Settings` only ever sees the plain `password`/`token` keys.
+
+use std::collections::HashMap;
+
+use config::{Value, ValueKind};
+use secrecy::{ExposeSecret, Secret};
+
+use crate::configuration::ConfigProblem;
+
+/// The settings which may be given by reference.
+/// A new secret setting must be added here to support `<key>_file`, `<key>_env`, ...
+pub const SECRET_KEYS: &[&str] = &[
+    "database.user.password",
+    "api.token",
+    "bot_protection.form_secret",
+];
+
+/// Where the secrets can be fetched from.
+///
+/// A provider handles the references with its suffix: `password_file` goes to the provider
+/// whose `suffix()` is `"file"`. Plug a new one (e.g. a vault client) in `default_providers`.
+pub trait SecretProvider {
+    fn suffix(&self) -> &'static str;
+    fn fetch(&self, reference: &str) -> Result<Secret<String>, String>;
+}
+
+/// `<key>_file: <path>`: the content of the file (without its trailing line break)
+pub struct FileSecretProvider;
+
+impl SecretProvider for FileSecretProvider {
+    fn suffix(&self) -> &'static str {
+        "file"
+    }
+
+    fn fetch(&self, reference: &str) -> Result<Secret<String>, String> {
+        let content = std::fs::read_to_string(reference)
+            .map_err(|e| format!("cannot read {}: {}", reference, e))?;
+        // `echo secret > file` appends a `\n`, which is never part of the secret
+        Ok(Secret::new(
+            content.trim_end_matches(['\n', '\r']).to_string(),
+        ))
+    }
+}
+
+/// `<key>_env: <NAME>`: the value of the `NAME` environment variable
+pub struct EnvSecretProvider {
+    // A snapshot, rather than `std::env::var`: tests provide their own variables
+    vars: HashMap<String, String>,
+}
+
+impl EnvSecretProvider {
+    pub fn new(vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        Self {
+            vars: vars.into_iter().collect(),
+        }
+    }
+}
+
+impl SecretProvider for EnvSecretProvider {
+    fn suffix(&self) -> &'static str {
+        "env"
+    }
+
+    fn fetch(&self, reference: &str) -> Result<Secret<String>, String> {
+        self.vars
+            .get(reference)
+            .map(|value| Secret::new(value.clone()))
+            .ok_or_else(|| format!("environment variable {} is not set", reference))
+    }
+}
+
+pub fn default_providers(
+    env_vars: impl IntoIterator<Item = (String, String)>,
+) -> Vec<Box<dyn SecretProvider>> {
+    vec![
+        Box::new(FileSecretProvider),
+        Box::new(EnvSecretProvider::new(env_vars)),
+    ]
+}
+
+/// Replaces every `<secret key>_<suffix>` reference of the merged configuration `tree`
+/// by the secret it points at. A reference takes precedence over a plain value
+/// (e.g. the local password of `base.yaml`).
+///
+/// Returns every reference that could not be resolved.
+pub fn resolve_secrets(
+    tree: &mut Value,
+    providers: &[Box<dyn SecretProvider>],
+) -> Vec<ConfigProblem> {
+    let mut problems = vec![];
+    for key in SECRET_KEYS {
+        let (parent, name) = key.rsplit_once('.').unwrap_or(("", key));
+        let Some(table) = table_at(tree, parent) else {
+            continue;
+        };
+        let references: Vec<_> = providers
+            .iter()
+            .filter_map(|provider| {
+                let reference_key = format!("{}_{}", name, provider.suffix());
+                table
+                    .remove(&reference_key)
+                    .map(|reference| (provider, reference_key, reference))
+            })
+            .collect();
+
+        match references.as_slice() {
+            [] => {}
+            [(provider, reference_key, reference)] => {
+                // On failure, an empty placeholder keeps the deserialization going:
+                // the other problems of the configuration are still found and reported.
+                table.insert(
+                    name.to_string(),
+                    Value::new(None, ValueKind::String(String::new())),
+                );
+                let problem = |message: String| ConfigProblem {
+                    key: format!("{}.{}", parent, reference_key),
+                    source: reference.origin().map(str::to_string),
+                    message,
+                };
+                let Ok(reference_value) = reference.clone().into_string() else {
+                    problems.push(problem("must be a string".to_string()));
+                    continue;
+                };
+                match provider.fetch(&reference_value) {
+                    Ok(secret) => {
+                        // The reference (path, variable name) shows up as the source in error reports
+                        table.insert(
+                            name.to_string(),
+                            Value::new(
+                                Some(&reference_value),
+                                ValueKind::String(secret.expose_secret().clone()),
+                            ),
+                        );
+                    }
+                    Err(message) => problems.push(problem(message)),
+                }
+            }
+            _ => problems.push(ConfigProblem {
+                key: key.to_string(),
+                source: None,
+                message: format!(
+                    "given by several references ({}): keep only one",
+                    references
+                        .iter()
+                        .map(|(_, reference_key, _)| reference_key.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }),
+        }
+    }
+    problems
+}
+
+// The table at a dotted `path` ("" being the root), if there is one
+fn table_at<'a>(tree: &'a mut Value, path: &str) -> Option<&'a mut config::Map<String, Value>> {
+    let mut current = tree;
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        let ValueKind::Table(table) = &mut current.kind else {
+            return None;
+        };
+        current = table.get_mut(segment)?;
+    }
+    match &mut current.kind {
+        ValueKind::Table(table) => Some(table),
+        _ => None,
+    }
+}
@@ -0,0 +1,67 @@
+//! src/security_headers.rs
+//! Browser hardening headers, added to every response of the app (errors included).
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{
+    CONTENT_SECURITY_POLICY, CONTENT_TYPE, HeaderValue, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY,
+    X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+use actix_web::middleware::Next;
+use actix_web::web;
+
+// One year. No `includeSubDomains`: the other subdomains are none of our business
+const HSTS: &str = "max-age=31536000";
+
+// For our HTML pages only (`/docs`, see `openapi::docs_ui`): the page itself comes from us,
+// the docs UI script (and its styles, fonts) from its CDN.
+// `frame-ancestors` is the modern take on `X-Frame-Options`.
+const HTML_CSP: &str = "default-src 'self'; \
+    script-src 'self' https://cdn.jsdelivr.net; \
+    style-src 'self' 'unsafe-inline' https://cdn.jsdelivr.net https://fonts.scalar.com; \
+    font-src 'self' data: https://fonts.scalar.com; \
+    img-src 'self' data:; \
+    object-src 'none'; \
+    base-uri 'none'; \
+    form-action 'self'; \
+    frame-ancestors 'none'";
+
+/// Registered as app data by `startup::run`: whether the app serves HTTPS itself.
+pub struct ServesHttps(pub bool);
+
+/// Middleware (`middleware::from_fn`) adding the security headers to the responses.
+///
+/// HSTS is only sent when we terminate TLS ourselves: behind a TLS-terminating proxy,
+/// the proxy is the one to send it.
+pub async fn security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let serves_https = req
+        .app_data::<web::Data<ServesHttps>>()
+        .is_some_and(|https| https.0);
+    let mut response = next.call(req).await?;
+
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    let headers = response.headers_mut();
+    // No guessing a script out of a JSON body (MIME sniffing)
+    headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    // Our URLs may carry ids: other sites only learn our origin
+    headers.insert(
+        REFERRER_POLICY,
+        HeaderValue::from_static("strict-origin-when-cross-origin"),
+    );
+    // Nothing of ours is meant to be framed (clickjacking)
+    headers.insert(X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+    if is_html {
+        headers.insert(CONTENT_SECURITY_POLICY, HeaderValue::from_static(HTML_CSP));
+    }
+    if serves_https {
+        headers.insert(STRICT_TRANSPORT_SECURITY, HeaderValue::from_static(HSTS));
+    }
+    Ok(response)
+}
@@ -1,13 +1,41 @@
-use actix_web::{App, HttpServer, dev::Server, web};
+use actix_web::http::header;
+use actix_web::middleware::{ErrorHandlers, from_fn};
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, dev::Server, web};
+use rustls::ServerConfig;
 use sqlx::PgPool;
 use std::net::TcpListener;
+use std::sync::Arc;
 use tracing_actix_web::TracingLogger;
 
+use crate::cors::cors;
+use crate::csrf::refuse_cross_site_passwords;
+use crate::listener::Listener;
+use crate::metrics::{self, Metrics};
+use crate::openapi::{docs_ui, openapi_json};
+use crate::problem::{
+    form_error_handler, json_error_handler, not_found, path_error_handler, query_error_handler,
+    render_problem,
+};
+use crate::rate_limit::{RateLimiter, rate_limit};
+use crate::reload::LiveSettings;
+use crate::routes::api;
 use crate::routes::health_check;
-use crate::routes::subscribe;
+use crate::routes::{form_token, subscribe};
+use crate::security_headers::{ServesHttps, security_headers};
+use crate::telemetry::AccessLogRootSpanBuilder;
 
 // NOTE: pub fn: public since it is not a binary entrypoint
-pub fn run(listener: TcpListener, db_conn_pool: PgPool) -> Result<Server, std::io::Error> {
+// `listeners`: already bound (one per configured address, TCP or Unix), all serving the same app
+// `live_settings`: shared with the `ConfigReloader`, which may swap them while we run.
+// `tls`: serve HTTPS rather than plain HTTP (see `tls::ReloadableCertificate`)
+// `rate_limiter`: the buckets of `POST /subscription` (see `rate_limit`)
+pub fn run(
+    listeners: Vec<Listener>,
+    db_conn_pool: PgPool,
+    live_settings: Arc<LiveSettings>,
+    tls: Option<ServerConfig>,
+    rate_limiter: RateLimiter,
+) -> Result<Server, std::io::Error> {
     /*
      * web::Data wraps our connection in an Atomic Reference Counted pointer, an Arc:
      * each instance of the application, instead of getting a raw copy of a PgPool,
@@ -16,9 +44,15 @@ pub fn run(listener: TcpListener, db_conn_pool: PgPool) -> Result<Server, std::i
      * and hands over a new copy of the memory address of the wrapped value.
      */
     let wrapped_clonable_db_conn = web::Data::new(db_conn_pool);
+    // Already an `Arc`: `web::Data::from` wraps it as is (no second allocation)
+    let live_settings = web::Data::from(live_settings);
+    // Shared by every worker: the limits hold for the whole process
+    let rate_limiter = web::Data::new(rate_limiter);
+    let app_metrics = web::Data::new(Metrics::default());
+    let serves_https = web::Data::new(ServesHttps(tls.is_some()));
 
     // HttpServer handles all transport level concerns
-    let server = HttpServer::new(
+    let mut server = HttpServer::new(
         // `move` transfers the ownership of `wrapped_clonable_db_conn`
         // from`server` to this zero-lambda closure
         move || {
@@ -30,26 +64,159 @@ pub fn run(listener: TcpListener, db_conn_pool: PgPool) -> Result<Server, std::i
             // App is the component whose job is to take an incoming request as input and spit out a response.
             App::new()
                 // Adding Middlewares with the `wrap` method on `App`
-                .wrap(TracingLogger::default()) // emits a log record for every incoming request.
-                .route(
-                    "/health_check",
-                    // web::get() creates a route guard that only matches HTTP GET requests
-                    // .to(health_check) binds the greet handler function to this route
-                    web::get().to(health_check),
+                // NOTE: the LAST registered middleware is the OUTERMOST one.
+                // Renders every error response (4xx/5xx) as `application/problem+json`
+                .wrap(ErrorHandlers::new().default_handler(render_problem))
+                // On every response, problems included (hence outside `ErrorHandlers`)
+                .wrap(from_fn(security_headers))
+                // emits a structured access-log record for every incoming request.
+                // Outermost: the request id it generates is needed by `render_problem`
+                .wrap(TracingLogger::<AccessLogRootSpanBuilder>::new())
+                // Extractor rejections are turned into problems rather than plain text
+                .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+                .app_data(web::FormConfig::default().error_handler(form_error_handler))
+                .app_data(web::QueryConfig::default().error_handler(query_error_handler))
+                .app_data(web::PathConfig::default().error_handler(path_error_handler))
+                // `web::resource` (rather than `.route`) answers `405 Method Not Allowed`
+                // to the methods it does not handle, instead of a misleading `404`.
+                .service(
+                    web::resource("/health_check")
+                        // web::get() creates a route guard that only matches HTTP GET requests
+                        // .to(health_check) binds the greet handler function to this route
+                        .route(web::get().to(health_check)),
                 )
-                .route(
-                    "/subscription",           // PATH: &str
-                    web::post().to(subscribe), // ROUTE: Route (an instance of the Route struct)
+                .service(
+                    web::resource("/subscription") // PATH: &str
+                        // Checked before the handler (and its body extractor) runs
+                        .wrap(from_fn(rate_limit))
+                        // Outside the rate limit: preflights are not counted,
+                        // a `429` is readable by the page (with its `Retry-After`)
+                        .wrap(from_fn(cors))
+                        .route(web::post().to(subscribe)), // ROUTE: Route (an instance of the Route struct)
                 )
+                .service(web::resource("/subscription/form-token").route(web::get().to(form_token)))
+                .service(
+                    web::scope("/api/v1")
+                        // Cached admin passwords are of no use to another site (see `csrf`)
+                        .wrap(from_fn(refuse_cross_site_passwords))
+                        // Preflights answered before any route (or the API token) is checked
+                        .wrap(from_fn(cors))
+                        .service(
+                            web::resource("/subscribers")
+                                .route(web::get().to(api::list_subscribers))
+                                .route(web::post().to(api::create_subscriber)),
+                        )
+                        .service(
+                            web::resource("/subscribers/{id}")
+                                .route(web::get().to(api::get_subscriber))
+                                .route(web::patch().to(api::update_subscriber))
+                                .route(web::delete().to(api::delete_subscriber)),
+                        )
+                        .service(
+                            web::resource("/email-domain-rules")
+                                .route(web::get().to(api::list_email_domain_rules))
+                                .route(web::post().to(api::create_email_domain_rule)),
+                        )
+                        .service(
+                            web::resource("/email-domain-rules/{domain}")
+                                .route(web::get().to(api::get_email_domain_rule))
+                                .route(web::put().to(api::update_email_domain_rule))
+                                .route(web::delete().to(api::delete_email_domain_rule)),
+                        )
+                        .service(
+                            web::resource("/api-keys")
+                                .route(web::get().to(api::list_api_keys))
+                                .route(web::post().to(api::create_api_key)),
+                        )
+                        .service(
+                            web::resource("/api-keys/{id}")
+                                .route(web::delete().to(api::revoke_api_key)),
+                        )
+                        .service(web::resource("/users").route(web::get().to(api::list_users)))
+                        .service(
+                            web::resource("/users/{username}/role")
+                                .route(web::put().to(api::update_user_role)),
+                        )
+                        .service(web::resource("/metrics").route(web::get().to(metrics::metrics))),
+                )
+                .service(web::resource("/openapi.json").route(web::get().to(openapi_json)))
+                // `/docs`, when built with the `docs-ui` feature
+                .configure(docs_ui)
+                // Any request that did not match a resource
+                .default_service(web::to(not_found))
                 // Register a PgPool as part of our application state
                 // byt getting a pointer copy and attach it to the application state
                 .app_data(wrapped_clonable_db_conn.clone())
+                // Read by `AccessLogRootSpanBuilder` to resolve the client IP
+                // and by the `ApiCaller` extractor to authenticate API calls
+                .app_data(live_settings.clone())
+                .app_data(rate_limiter.clone())
+                .app_data(app_metrics.clone())
+                .app_data(serves_https.clone())
         },
-    )
-    .listen(listener)?
-    .run(); // Returns a Future (NOTA: lazy in rust - pure description of work - doesn't execute yet!)
+    );
+    for listener in listeners {
+        server = match (listener, &tls) {
+            // NOTE: actix-web adds the ALPN protocols (h2, http/1.1) to the config itself
+            (Listener::Tcp(listener), Some(tls)) => {
+                server.listen_rustls_0_23(listener, tls.clone())?
+            }
+            (Listener::Tcp(listener), None) => server.listen(listener)?,
+            // Always plain HTTP: only local processes (e.g. a sidecar proxy) can connect
+            #[cfg(unix)]
+            (Listener::Unix(listener), _) => server.listen_uds(listener)?,
+        };
+    }
+    let server = server.run(); // Returns a Future (NOTA: lazy in rust - pure description of work - doesn't execute yet!)
 
     // We return the server without awaiting it,
     // i.e, it can run in the background, concurrently with downstream futures and tasks
     Ok(server) // NOTE: Server IS A FUTURE WRAPPED IN A RESULT !!!
 }
+
+/// Plain HTTP listeners answering every request with a redirect to the same URL over HTTPS,
+/// `https_port` being where the HTTPS listeners are.
+pub fn run_redirect(
+    listeners: Vec<TcpListener>,
+    https_port: u16,
+) -> Result<Server, std::io::Error> {
+    let mut server = HttpServer::new(move || {
+        App::new()
+            .wrap(TracingLogger::<AccessLogRootSpanBuilder>::new())
+            .app_data(web::Data::new(HttpsPort(https_port)))
+            .default_service(web::to(redirect_to_https))
+    });
+    for listener in listeners {
+        server = server.listen(listener)?;
+    }
+    Ok(server.run())
+}
+
+struct HttpsPort(u16);
+
+// `308 Permanent Redirect` rather than `301`: the method and the body are kept (e.g. a POST)
+async fn redirect_to_https(request: HttpRequest, https_port: web::Data<HttpsPort>) -> HttpResponse {
+    // The `Host` the client asked for, minus its (plain HTTP) port
+    let connection_info = request.connection_info();
+    let host = connection_info.host();
+    let host = match host.rsplit_once(':') {
+        // `[::1]:80` -> `[::1]`, but `[::1]` stays as is
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    let authority = match https_port.0 {
+        443 => host.to_string(),
+        port => format!("{}:{}", host, port),
+    };
+    let path_and_query = request
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    HttpResponse::PermanentRedirect()
+        .insert_header((
+            header::LOCATION,
+            format!("https://{}{}", authority, path_and_query),
+        ))
+        .finish()
+}
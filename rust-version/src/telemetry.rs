@@ -1,9 +1,58 @@
-use tracing::Subscriber;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{Error, HttpMessage, web};
 use tracing::subscriber::set_global_default;
+use tracing::{Span, Subscriber};
+use tracing_actix_web::{RequestId, RootSpanBuilder};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
 use tracing_subscriber::fmt::MakeWriter;
-use tracing_subscriber::{EnvFilter, Registry, layer::SubscriberExt};
+use tracing_subscriber::layer::Layered;
+use tracing_subscriber::{EnvFilter, Layer, Registry, layer::SubscriberExt, reload};
+
+use crate::reload::LiveSettings;
+
+/// The shape of the log lines emitted by the subscriber.
+///
+/// Chosen through the `telemetry.format` setting (see `configuration`).
+/// - `bunyan`: Bunyan-compatible JSON, meant to be piped through the `bunyan` CLI
+/// - `json`: plain JSON lines, one object per event (for log aggregators)
+/// - `pretty`: multi-line, human-readable output (local development)
+/// - `compact`: single-line, human-readable output
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Bunyan,
+    Json,
+    Pretty,
+    Compact,
+}
+
+impl LogFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogFormat::Bunyan => "bunyan",
+            LogFormat::Json => "json",
+            LogFormat::Pretty => "pretty",
+            LogFormat::Compact => "compact",
+        }
+    }
+}
+
+// The `EnvFilter` sits behind a `reload::Layer`: it can be swapped while the app runs.
+type FilterLayer = reload::Layer<EnvFilter, Registry>;
+
+// The layers sitting on top of the `EnvFilter`: the concrete type differs for each format,
+// hence the trait object (same trick as `impl Trait`, but resolved at runtime).
+type FormattingLayer = Box<dyn Layer<Layered<FilterLayer, Registry>> + Send + Sync>;
+
+// Handle on the filter of the LAST subscriber built by `get_subscriber`.
+// Global, just like the subscriber itself once `init_subscriber` has been called.
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
 
 /// Compose multiple layers into a `tracing`'s subscriber.
 ///
@@ -13,9 +62,13 @@ use tracing_subscriber::{EnvFilter, Registry, layer::SubscriberExt};
 /// spell out the actual type of the returned subscriber, which is indeed quite complex.
 /// We need to explicitly call out that the returned subscriber is
 /// `Send` and `Sync` to make it possible to pass it to `init_subscriber` later on.
+///
+/// The formatting layer is boxed so that the `format` can be picked at runtime
+/// while still returning a single opaque type.
 pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
+    format: LogFormat,
     sink: Sink,
 ) -> impl Subscriber + Send + Sync
 where
@@ -26,19 +79,45 @@ where
 {
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
+    let (env_filter, handle) = reload::Layer::new(env_filter);
+    let _ = LOG_FILTER.set(handle);
 
-    let formatting_layer = BunyanFormattingLayer::new(
-        name, sink, // i.e, where should go the formatted spans
-    );
+    let formatting_layer: FormattingLayer = match format {
+        LogFormat::Bunyan => Box::new(JsonStorageLayer.and_then(BunyanFormattingLayer::new(
+            name, sink, // i.e, where should go the formatted spans
+        ))),
+        LogFormat::Json => Box::new(
+            tracing_subscriber::fmt::layer()
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_writer(sink),
+        ),
+        LogFormat::Pretty => Box::new(tracing_subscriber::fmt::layer().pretty().with_writer(sink)),
+        LogFormat::Compact => {
+            Box::new(tracing_subscriber::fmt::layer().compact().with_writer(sink))
+        }
+    };
 
     Registry::default()
         // `.with` is provided by `SubscriberExt`
         // an extension trait for `Subscriber` exposed by `tracing_subscriber`
         .with(env_filter)
-        .with(JsonStorageLayer)
         .with(formatting_layer)
 }
 
+/// Replace the filter (e.g. `info`, `zero2prod=debug,sqlx=warn`) of the global subscriber.
+/// A no-op when `RUST_LOG` is set: it overrides the configuration, at startup and afterwards.
+pub fn reload_log_level(directives: &str) -> Result<(), String> {
+    if std::env::var("RUST_LOG").is_ok() {
+        return Ok(());
+    }
+    let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+    let handle = LOG_FILTER.get().ok_or("No subscriber was built")?;
+    handle.reload(filter).map_err(|e| e.to_string())
+}
+
 /// Register a subscriber as global default to process span data.
 /// It should only be called once!
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
@@ -48,3 +127,210 @@ pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     // specify which subscriber should process the span
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
+
+/// Proxies whose `X-Forwarded-For` header we are willing to believe.
+///
+/// Part of the `LiveSettings` registered as application state in `startup::run`,
+/// so that it can be retrieved by the root span builder (which has no `self` to hold it).
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(Vec<IpAddr>);
+
+impl TrustedProxies {
+    pub fn new(proxies: Vec<IpAddr>) -> Self {
+        Self(proxies)
+    }
+
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.0.contains(ip)
+    }
+
+    /// Resolve the IP of the client that originated the request.
+    ///
+    /// `X-Forwarded-For` is trivially spoofable: anyone can send it.
+    /// We only honour it when the TCP peer is one of our trusted proxies,
+    /// and we walk it right-to-left (each proxy APPENDS the address it saw),
+    /// stopping at the first hop that is not a trusted proxy.
+    pub fn client_ip(&self, peer: Option<IpAddr>, forwarded_for: Option<&str>) -> Option<IpAddr> {
+        let peer = peer?;
+        if !self.is_trusted(&peer) {
+            return Some(peer);
+        }
+        let Some(forwarded_for) = forwarded_for else {
+            return Some(peer);
+        };
+        let mut client = peer;
+        for hop in forwarded_for.rsplit(',') {
+            match hop.trim().parse::<IpAddr>() {
+                Ok(ip) => {
+                    client = ip;
+                    if !self.is_trusted(&ip) {
+                        break;
+                    }
+                }
+                // A garbled entry: we cannot trust anything on its left.
+                Err(_) => break,
+            }
+        }
+        Some(client)
+    }
+}
+
+// When the request started being processed, stashed in the request extensions
+// by `on_request_start` and read back by `on_request_end` to compute the latency.
+#[derive(Clone, Copy)]
+struct RequestStart(Instant);
+
+/// Root span builder emitting one structured access-log record per request.
+///
+/// # Implementation Notes
+///
+/// - `http.route` is the *matched pattern* (e.g. `/api/v1/subscribers/{id}`), never the raw path:
+///   raw paths have unbounded cardinality, which would blow up any log/metrics backend.
+/// - `tracing` spans get their level once and for all at creation time,
+///   when the status code is not known yet. For 5xx responses, we therefore
+///   flag the span (`otel.status_code = "ERROR"`) and emit the access-log event at `ERROR` level.
+pub struct AccessLogRootSpanBuilder;
+
+impl RootSpanBuilder for AccessLogRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        request
+            .extensions_mut()
+            .insert(RequestStart(Instant::now()));
+
+        let trusted_proxies = request
+            .app_data::<web::Data<LiveSettings>>()
+            .map(|live| live.current().trusted_proxies.clone())
+            .unwrap_or_default();
+        let forwarded_for = request
+            .headers()
+            .get("X-Forwarded-For")
+            .and_then(|h| h.to_str().ok());
+        let client_ip = trusted_proxies
+            .client_ip(request.peer_addr().map(|addr| addr.ip()), forwarded_for)
+            .map(|ip| ip.to_string())
+            .unwrap_or_default();
+
+        let user_agent = request
+            .headers()
+            .get("User-Agent")
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or("");
+        let route = request.match_pattern().unwrap_or_else(|| "default".into());
+        let request_id = request
+            .extensions()
+            .get::<RequestId>()
+            .map(|id| id.to_string())
+            .unwrap_or_default();
+
+        tracing::info_span!(
+            "HTTP request",
+            http.method = %request.method(),
+            http.route = %route,
+            http.client_ip = %client_ip,
+            http.user_agent = %user_agent,
+            http.status_code = tracing::field::Empty,
+            http.response_size = tracing::field::Empty,
+            http.latency_ms = tracing::field::Empty,
+            otel.status_code = tracing::field::Empty,
+            request_id = %request_id,
+            exception.message = tracing::field::Empty,
+        )
+    }
+
+    fn on_request_end<B: MessageBody>(span: Span, outcome: &Result<ServiceResponse<B>, Error>) {
+        let (status, latency) = match outcome {
+            Ok(response) => {
+                if let BodySize::Sized(size) = response.response().body().size() {
+                    span.record("http.response_size", size);
+                }
+                if let Some(error) = response.response().error() {
+                    span.record("exception.message", tracing::field::display(error));
+                }
+                let latency = response
+                    .request()
+                    .extensions()
+                    .get::<RequestStart>()
+                    .map(|start| start.0.elapsed());
+                (response.status(), latency)
+            }
+            Err(error) => {
+                span.record("exception.message", tracing::field::display(error));
+                (error.as_response_error().status_code(), None)
+            }
+        };
+
+        span.record("http.status_code", status.as_u16());
+        if let Some(latency) = latency {
+            span.record("http.latency_ms", latency.as_millis() as u64);
+        }
+
+        // Wrapping the event in the span makes it inherit all of the above fields.
+        let _guard = span.enter();
+        if status.is_server_error() {
+            span.record("otel.status_code", "ERROR");
+            tracing::error!("Request failed");
+        } else {
+            span.record("otel.status_code", "OK");
+            tracing::info!("Request completed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::TrustedProxies;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    // Two proxies in front of the app: a load balancer, then an ingress
+    fn proxies() -> TrustedProxies {
+        TrustedProxies::new(vec![ip("10.0.0.1"), ip("10.0.0.2")])
+    }
+
+    #[test]
+    fn an_untrusted_peer_cannot_spoof_its_ip() {
+        let client_ip = proxies().client_ip(Some(ip("198.51.100.7")), Some("203.0.113.9"));
+        assert_eq!(client_ip, Some(ip("198.51.100.7")));
+    }
+
+    #[test]
+    fn the_first_untrusted_hop_from_the_right_is_the_client() {
+        // What the client sent itself (`1.1.1.1`) is left of the real client: ignored
+        let client_ip =
+            proxies().client_ip(Some(ip("10.0.0.1")), Some("1.1.1.1, 203.0.113.9, 10.0.0.2"));
+        assert_eq!(client_ip, Some(ip("203.0.113.9")));
+    }
+
+    #[test]
+    fn an_all_trusted_chain_resolves_to_its_leftmost_hop() {
+        let client_ip = proxies().client_ip(Some(ip("10.0.0.1")), Some("10.0.0.1, 10.0.0.2"));
+        assert_eq!(client_ip, Some(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn a_garbled_entry_stops_at_the_last_good_hop() {
+        let client_ip = proxies().client_ip(
+            Some(ip("10.0.0.1")),
+            Some("203.0.113.9, not-an-ip, 10.0.0.2"),
+        );
+        assert_eq!(client_ip, Some(ip("10.0.0.2")));
+    }
+
+    #[test]
+    fn a_trusted_peer_without_header_is_the_client() {
+        assert_eq!(
+            proxies().client_ip(Some(ip("10.0.0.1")), None),
+            Some(ip("10.0.0.1"))
+        );
+    }
+
+    #[test]
+    fn no_peer_no_ip() {
+        // Unix socket: there is no TCP peer, whatever the header says
+        assert_eq!(proxies().client_ip(None, Some("203.0.113.9")), None);
+    }
+}
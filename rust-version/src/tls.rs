@@ -0,0 +1,139 @@
+//! src/tls.rs
+//! Native HTTPS (rustls), for the deployments without a TLS-terminating proxy in front.
+//!
+//! ```yaml
+//! server:
+//!   port: 443
+//!   tls:
+//!     cert_path: /etc/zero2prod/tls/cert.pem # the full chain, leaf first
+//!     key_path: /etc/zero2prod/tls/key.pem
+//!     redirect_port: 80                      # optional: plain HTTP, redirected to HTTPS
+//! ```
+//!
+//! The certificate is reloaded whenever its files change (e.g. renewed by certbot/cert-manager):
+//! only the NEW handshakes get the new one, established connections are left alone.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use rustls::ServerConfig;
+use rustls::crypto::{CryptoProvider, ring};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+
+use crate::configuration::TlsSettings;
+
+/// The certificate presented to the clients, swapped in place on reload.
+///
+/// rustls asks the resolver for a certificate on every handshake: replacing the `CertifiedKey`
+/// is enough, no need to rebuild the `ServerConfig` (nor to restart the listeners).
+#[derive(Debug)]
+pub struct ReloadableCertificate {
+    settings: TlsSettings,
+    current: ArcSwap<CertifiedKey>,
+}
+
+impl ReloadableCertificate {
+    /// Fails when the certificate or the key cannot be read: better not to start at all.
+    pub fn load(settings: &TlsSettings) -> std::io::Result<Self> {
+        Ok(Self {
+            settings: settings.clone(),
+            current: ArcSwap::from_pointee(load_certified_key(settings)?),
+        })
+    }
+
+    /// Re-reads the files. On failure (e.g. a half-written file), the current certificate stays.
+    pub fn reload(&self) -> std::io::Result<()> {
+        self.current
+            .store(Arc::new(load_certified_key(&self.settings)?));
+        Ok(())
+    }
+
+    /// The rustls configuration of the HTTPS listeners, resolving to this certificate.
+    pub fn server_config(self: &Arc<Self>) -> std::io::Result<ServerConfig> {
+        Ok(
+            ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(std::io::Error::other)?
+                .with_no_client_auth()
+                .with_cert_resolver(self.clone()),
+        )
+    }
+
+    /// Reloads the certificate whenever its files change.
+    ///
+    /// Watching stops when the returned watcher is dropped: keep it alive.
+    pub fn watch(self: Arc<Self>) -> notify::Result<RecommendedWatcher> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        // The DIRECTORIES are watched: renewal tools usually swap the files (or a symlink)
+        // rather than writing to them, which a watch on the files themselves would miss.
+        let cert_dir = parent_dir(&self.settings.cert_path);
+        let key_dir = parent_dir(&self.settings.key_path);
+        watcher.watch(&cert_dir, RecursiveMode::NonRecursive)?;
+        if key_dir != cert_dir {
+            watcher.watch(&key_dir, RecursiveMode::NonRecursive)?;
+        }
+
+        std::thread::spawn(move || {
+            // Same as `ConfigReloader::watch`: the thread ends once the watcher is dropped
+            while let Ok(event) = rx.recv() {
+                if event.is_err() {
+                    continue;
+                }
+                // The certificate and the key are rarely written at once: let things settle
+                while rx.recv_timeout(Duration::from_millis(200)).is_ok() {}
+                match self.reload() {
+                    Ok(()) => tracing::info!("TLS certificate reloaded"),
+                    Err(e) => tracing::error!(error = %e, "TLS certificate reload rejected"),
+                }
+            }
+        });
+        Ok(watcher)
+    }
+}
+
+impl ResolvesServerCert for ReloadableCertificate {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.load_full())
+    }
+}
+
+fn load_certified_key(settings: &TlsSettings) -> std::io::Result<CertifiedKey> {
+    let invalid = |path: &Path, e: &dyn std::fmt::Display| {
+        std::io::Error::other(format!("Invalid {}: {}", path.display(), e))
+    };
+    let chain = CertificateDer::pem_file_iter(&settings.cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid(&settings.cert_path, &e))?;
+    if chain.is_empty() {
+        return Err(invalid(&settings.cert_path, &"no certificate found"));
+    }
+    let key = PrivateKeyDer::from_pem_file(&settings.key_path)
+        .map_err(|e| invalid(&settings.key_path, &e))?;
+    let provider: &CryptoProvider = &ring::default_provider();
+    let signing_key = provider
+        .key_provider
+        .load_private_key(key)
+        .map_err(|e| invalid(&settings.key_path, &e))?;
+    let certified_key = CertifiedKey::new(chain, signing_key);
+    // e.g. a renewal caught halfway: the new certificate along with the old key
+    certified_key
+        .keys_match()
+        .map_err(|e| invalid(&settings.key_path, &e))?;
+    Ok(certified_key)
+}
+
+// `cert.pem` (relative to the current directory) has no parent to speak of: `.`
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
@@ -0,0 +1,243 @@
+//! tests/api/api_keys.rs
+//! `/api/v1/api-keys`, and the scopes the keys are issued with.
+
+use reqwest::Method;
+
+use crate::helpers::{Caller, TestApp, json_body, spawn_app};
+
+async fn create_key(app: &TestApp, body: serde_json::Value) -> reqwest::Response {
+    app.api_request(Method::POST, "/api-keys")
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+// The id of a fresh `integration` key granted `scopes`, and the key as a caller
+async fn issue_key(app: &TestApp, scopes: &[&str]) -> (String, Caller) {
+    let response = create_key(
+        app,
+        serde_json::json!({ "owner": "integration", "scopes": scopes }),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let created = json_body(response).await;
+    (
+        created["id"].as_str().unwrap().to_string(),
+        Caller::Key(created["key"].as_str().unwrap().to_string()),
+    )
+}
+
+async fn get_with(app: &TestApp, path: &str, caller: &Caller) -> reqwest::Response {
+    app.api_request_as(caller, Method::GET, path)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn a_key_is_only_granted_its_scopes() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let (_, key) = issue_key(&app, &["subscribers:read"]).await;
+    assert!(matches!(&key, Caller::Key(key) if key.starts_with("z2p_")));
+
+    // ACT
+    let allowed = get_with(&app, "/subscribers", &key).await;
+    let forbidden = get_with(&app, "/metrics", &key).await;
+
+    // ASSERT
+    assert_eq!(allowed.status().as_u16(), 200);
+    assert_eq!(forbidden.status().as_u16(), 403);
+    assert_eq!(
+        forbidden.headers()["WWW-Authenticate"],
+        r#"Bearer error="insufficient_scope", scope="metrics:read""#
+    );
+    assert_eq!(json_body(forbidden).await["code"], "insufficient_scope");
+    // A key cannot issue keys without `api_keys:manage` either
+    let response = app
+        .api_request_as(&key, Method::POST, "/api-keys")
+        .header("Content-Type", "application/json")
+        .body(serde_json::json!({"owner": "me", "scopes": ["metrics:read"]}).to_string())
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn a_key_cannot_mint_a_key_broader_than_itself() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let (_, manager) = issue_key(&app, &["api_keys:manage", "metrics:read"]).await;
+    let mint = |scopes: serde_json::Value| {
+        app.api_request_as(&manager, Method::POST, "/api-keys")
+            .header("Content-Type", "application/json")
+            .body(serde_json::json!({"owner": "ci", "scopes": scopes}).to_string())
+            .send()
+    };
+
+    // ACT
+    let broader = mint(serde_json::json!(["metrics:read", "users:manage"]))
+        .await
+        .unwrap();
+    let narrower = mint(serde_json::json!(["metrics:read"])).await.unwrap();
+
+    // ASSERT
+    assert_eq!(broader.status().as_u16(), 403);
+    assert_eq!(json_body(broader).await["code"], "insufficient_scope");
+    assert_eq!(narrower.status().as_u16(), 201);
+    let keys: i64 = sqlx::query_scalar("SELECT count(*) FROM api_keys")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .unwrap();
+    assert_eq!(keys, 2);
+}
+
+#[tokio::test]
+async fn revoked_expired_or_forged_keys_are_refused() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let (id, revoked) = issue_key(&app, &["metrics:read"]).await;
+    let (_, valid) = issue_key(&app, &["metrics:read"]).await;
+    let (expired_id, expired) = issue_key(&app, &["metrics:read"]).await;
+    sqlx::query("UPDATE api_keys SET expires_at = now() - interval '1 minute' WHERE id = $1::uuid")
+        .bind(&expired_id)
+        .execute(&app.db_conn_pool)
+        .await
+        .unwrap();
+    // The right prefix, another secret
+    let Caller::Key(key) = &valid else {
+        unreachable!()
+    };
+    let (prefix, _) = key.rsplit_once('_').unwrap();
+    let forged = Caller::Key(format!("{}_{}", prefix, "0".repeat(48)));
+
+    // ACT
+    let response = app
+        .api_request(Method::DELETE, &format!("/api-keys/{}", id))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 204);
+    for key in [&revoked, &expired, &forged] {
+        let response = get_with(&app, "/metrics", key).await;
+        assert_eq!(response.status().as_u16(), 401, "{:?}", key);
+        assert_eq!(json_body(response).await["code"], "unauthorized");
+    }
+    assert_eq!(
+        get_with(&app, "/metrics", &valid).await.status().as_u16(),
+        200
+    );
+    // Revoking twice: there is no active key with this id anymore
+    let response = app
+        .api_request(Method::DELETE, &format!("/api-keys/{}", id))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 404);
+    assert_eq!(json_body(response).await["code"], "api_key_not_found");
+}
+
+#[tokio::test]
+async fn keys_are_listed_without_their_secret() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let (id, key) = issue_key(&app, &["subscribers:read", "subscribers:read"]).await;
+    get_with(&app, "/subscribers", &key).await;
+    let Caller::Key(key) = key else {
+        unreachable!()
+    };
+
+    // ACT
+    let response = app
+        .api_request(Method::GET, "/api-keys")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    let (_, secret) = key.rsplit_once('_').unwrap();
+    assert!(!body.contains(secret));
+    let keys: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(keys[0]["id"], id.as_str());
+    assert_eq!(keys[0]["owner"], "integration");
+    assert_eq!(keys[0]["scopes"], serde_json::json!(["subscribers:read"]));
+    assert!(keys[0]["last_used_at"].is_string());
+    assert!(keys[0].get("key").is_none());
+    // Only the hash is stored
+    let stored: String = sqlx::query_scalar("SELECT key_hash FROM api_keys")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .unwrap();
+    assert!(!stored.contains(secret));
+}
+
+#[tokio::test]
+async fn invalid_keys_are_rejected_with_a_422() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"owner": " ", "scopes": ["metrics:read"]}),
+            "invalid_owner",
+        ),
+        (
+            serde_json::json!({"owner": "ci", "scopes": []}),
+            "invalid_scopes",
+        ),
+        (
+            serde_json::json!({
+                "owner": "ci",
+                "scopes": ["metrics:read"],
+                "expires_at": "2000-01-01T00:00:00Z"
+            }),
+            "invalid_expiry",
+        ),
+    ];
+
+    for (body, code) in test_cases {
+        // ACT
+        let response = create_key(&app, body).await;
+
+        // ASSERT
+        assert_eq!(response.status().as_u16(), 422, "{}", code);
+        assert_eq!(json_body(response).await["code"], code);
+    }
+}
+
+#[tokio::test]
+async fn a_key_cannot_revoke_a_key_broader_than_itself() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let (_, manager) = issue_key(&app, &["api_keys:manage", "metrics:read"]).await;
+    let (broader_id, broader) = issue_key(&app, &["subscribers:write"]).await;
+    let (narrower_id, _) = issue_key(&app, &["metrics:read"]).await;
+    let revoke = |id: &str| {
+        app.api_request_as(&manager, Method::DELETE, &format!("/api-keys/{}", id))
+            .send()
+    };
+
+    // ACT
+    let refused = revoke(&broader_id).await.unwrap();
+    let revoked = revoke(&narrower_id).await.unwrap();
+
+    // ASSERT
+    assert_eq!(refused.status().as_u16(), 403);
+    assert_eq!(json_body(refused).await["code"], "insufficient_scope");
+    assert_eq!(revoked.status().as_u16(), 204);
+    // Still in use
+    let response = app
+        .api_request_as(&broader, Method::POST, "/subscribers")
+        .header("Content-Type", "application/json")
+        .body(serde_json::json!({"email": "ursula@example.com", "name": "le guin"}).to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+}
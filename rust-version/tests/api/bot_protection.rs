@@ -0,0 +1,151 @@
+//! tests/api/bot_protection.rs
+//! Honeypot field and signed form tokens of the signup form.
+
+use chrono::{Duration, Utc};
+use secrecy::Secret;
+use zero2prod::bot_protection::issue_form_token;
+use zero2prod::configuration::{RateLimitStore, Settings};
+
+use crate::helpers::{TestApp, json_body, spawn_app_with};
+
+const FORM_SECRET: &str = "form-secret";
+
+fn protect(config: &mut Settings, min_fill_seconds: u64) {
+    config.bot_protection.enabled = true;
+    config.bot_protection.form_secret = Secret::new(FORM_SECRET.to_string());
+    config.bot_protection.min_fill_seconds = min_fill_seconds;
+    config.bot_protection.max_age_seconds = 3600;
+}
+
+async fn saved_subscriptions(app: &TestApp) -> i64 {
+    sqlx::query_scalar("SELECT count(*) FROM subscriptions")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn a_form_filled_in_with_a_fresh_token_is_saved() {
+    // ARRANGE
+    let app = spawn_app_with(|config| protect(config, 0)).await;
+    let response = app
+        .api_client
+        .get(format!("{}/subscription/form-token", app.root_address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.headers()["Cache-Control"], "no-store");
+    let token = json_body(response).await;
+    assert_eq!(token["honeypot_field"], "website");
+
+    // ACT
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "website": "",
+        "form_token": token["form_token"],
+    });
+    let response = app
+        .post_subscriptions_as(body.to_string(), "application/json")
+        .await;
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(saved_subscriptions(&app).await, 1);
+}
+
+#[tokio::test]
+async fn bots_are_answered_200_but_dropped_and_counted() {
+    // ARRANGE
+    let app = spawn_app_with(|config| protect(config, 3)).await;
+    let secret = Secret::new(FORM_SECRET.to_string());
+    let filled_in_10s_ago = issue_form_token(&secret, Utc::now() - Duration::seconds(10));
+    let just_now = issue_form_token(&secret, Utc::now());
+    let two_hours_ago = issue_form_token(&secret, Utc::now() - Duration::hours(2));
+    let forged = issue_form_token(&Secret::new("guessed".into()), Utc::now());
+    let test_cases = vec![
+        (
+            format!("website=spam&form_token={}", filled_in_10s_ago),
+            "honeypot",
+        ),
+        (String::new(), "missing_token"),
+        (format!("form_token={}", forged), "invalid_token"),
+        (format!("form_token={}", just_now), "too_fast"),
+        (format!("form_token={}", two_hours_ago), "expired"),
+    ];
+
+    for (fields, description) in &test_cases {
+        // ACT
+        let body = format!("name=bot&email=bot%40example.com&{}", fields);
+        let response = app.post_subscriptions(body).await;
+
+        // ASSERT
+        // Indistinguishable from a successful subscription
+        assert_eq!(response.status().as_u16(), 200, "{}", description);
+        assert_eq!(response.text().await.unwrap(), "", "{}", description);
+    }
+    assert_eq!(saved_subscriptions(&app).await, 0);
+    let metrics = app
+        .api_request(reqwest::Method::GET, "/metrics")
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .text()
+        .await
+        .unwrap();
+    for (_, reason) in test_cases {
+        let series = format!("zero2prod_bot_rejections_total{{reason=\"{}\"}} 1", reason);
+        assert!(metrics.contains(&series), "{} not in:\n{}", series, metrics);
+    }
+}
+
+#[tokio::test]
+async fn a_token_is_spent_on_the_first_accepted_submission_only() {
+    // Spent tokens are kept in the store of the rate limiter, whichever it is
+    for store in [RateLimitStore::Memory, RateLimitStore::Postgres] {
+        // ARRANGE
+        let app = spawn_app_with(|config| {
+            protect(config, 3);
+            config.rate_limit.store = store;
+        })
+        .await;
+        let secret = Secret::new(FORM_SECRET.to_string());
+        let token = issue_form_token(&secret, Utc::now() - Duration::seconds(10));
+        let submit = |email: &str| {
+            app.post_subscriptions(format!(
+                "name=le%20guin&email={}&form_token={}",
+                email, token
+            ))
+        };
+
+        // ACT
+        // A typo first: refused, and the token is not spent on it
+        let typo = submit("ursula%40").await;
+        let fixed = submit("ursula%40example.com").await;
+        // The same token, collected once and replayed by a script
+        let replayed = submit("bot%40example.com").await;
+
+        // ASSERT
+        assert_eq!(typo.status().as_u16(), 422, "{:?}", store);
+        assert_eq!(fixed.status().as_u16(), 200, "{:?}", store);
+        assert_eq!(replayed.status().as_u16(), 200, "{:?}", store);
+        let saved: Vec<String> = sqlx::query_scalar("SELECT email FROM subscriptions")
+            .fetch_all(&app.db_conn_pool)
+            .await
+            .unwrap();
+        assert_eq!(saved, vec!["ursula@example.com"], "{:?}", store);
+        let metrics = app
+            .api_request(reqwest::Method::GET, "/metrics")
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap();
+        assert!(
+            metrics.contains("zero2prod_bot_rejections_total{reason=\"replayed\"} 1"),
+            "{:?}",
+            store
+        );
+    }
+}
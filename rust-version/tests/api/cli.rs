@@ -0,0 +1,128 @@
+//! tests/api/cli.rs
+//! The admin subcommands of the `zero2prod` binary, called as library functions.
+
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use zero2prod::authentication::Role;
+use zero2prod::cli;
+
+use crate::helpers::{spawn_app, test_configuration};
+
+// Commands write to any `impl Write`: a Vec<u8> captures what the user would see.
+fn printed(out: Vec<u8>) -> String {
+    String::from_utf8(out).expect("The command output is not UTF-8")
+}
+
+#[tokio::test]
+async fn create_admin_stores_a_hash_of_the_printed_password_only() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let mut out = vec![];
+
+    // ACT
+    cli::create_admin(&app.db_conn_pool, "admin", Role::Owner, &mut out)
+        .await
+        .expect("Failed to create the admin");
+    let duplicate = cli::create_admin(&app.db_conn_pool, "admin", Role::Owner, &mut vec![]).await;
+
+    // ASSERT
+    let output = printed(out);
+    let password = output
+        .lines()
+        .find_map(|line| line.strip_prefix("Password (shown only once): "))
+        .expect("The password was not printed");
+    let saved = sqlx::query!("SELECT password_hash FROM users WHERE username = 'admin'")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .expect("Failed to fetch the admin");
+    assert_ne!(saved.password_hash, password);
+    let hash = PasswordHash::new(&saved.password_hash).unwrap();
+    assert!(
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    );
+    assert!(
+        duplicate.is_err(),
+        "A second admin with the same name was created"
+    );
+}
+
+#[tokio::test]
+async fn reset_password_replaces_the_hash_of_existing_users_only() {
+    // ARRANGE
+    let app = spawn_app().await;
+    cli::create_admin(&app.db_conn_pool, "admin", Role::Owner, &mut vec![])
+        .await
+        .unwrap();
+    let hash_of = || {
+        sqlx::query_scalar!("SELECT password_hash FROM users WHERE username = 'admin'")
+            .fetch_one(&app.db_conn_pool)
+    };
+    let before = hash_of().await.unwrap();
+
+    // ACT
+    let reset = cli::reset_password(&app.db_conn_pool, "admin", &mut vec![]).await;
+    let unknown = cli::reset_password(&app.db_conn_pool, "nobody", &mut vec![]).await;
+
+    // ASSERT
+    assert!(reset.is_ok());
+    assert_ne!(hash_of().await.unwrap(), before);
+    assert!(unknown.is_err());
+}
+
+#[tokio::test]
+async fn subscribers_export_then_delete_by_email() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.create_subscriber(serde_json::json!({
+        "email": "ursula_le_guin@gmail.com",
+        "name": "Le Guin, Ursula \"K.\""
+    }))
+    .await;
+
+    // ACT
+    let mut export = vec![];
+    cli::export_subscribers(&app.db_conn_pool, &mut export)
+        .await
+        .unwrap();
+    cli::delete_subscriber(&app.db_conn_pool, "ursula_le_guin@gmail.com", &mut vec![])
+        .await
+        .unwrap();
+    let mut list = vec![];
+    cli::list_subscribers(&app.db_conn_pool, None, &mut list)
+        .await
+        .unwrap();
+
+    // ASSERT
+    let export = printed(export);
+    let mut lines = export.lines();
+    assert_eq!(lines.next(), Some("id,email,name,status,subscribed_at"));
+    assert!(
+        lines.next().unwrap().contains(
+            r#",ursula_le_guin@gmail.com,"Le Guin, Ursula ""K.""",pending_confirmation,"#
+        )
+    );
+    assert_eq!(printed(list), "");
+    let audit = sqlx::query!("SELECT actor, action FROM audit_log")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .expect("The deletion was not audited");
+    assert_eq!(audit.actor, cli::CLI_ACTOR);
+    assert_eq!(audit.action, "subscriber.deleted");
+}
+
+#[test]
+fn config_check_prints_the_settings_without_their_secrets() {
+    // ARRANGE
+    let config = test_configuration();
+    let mut out = vec![];
+
+    // ACT
+    cli::config_check(&config, &mut out).unwrap();
+
+    // ASSERT
+    let output = printed(out);
+    assert!(output.contains("newsletter"));
+    assert!(output.contains("REDACTED"));
+    assert!(!output.contains("local-api-token"));
+}
@@ -0,0 +1,303 @@
+//! tests/api/configuration.rs
+//! Loading and validating the configuration (`configuration/*.yaml` + `APP_*` variables).
+
+use std::path::PathBuf;
+
+use secrecy::ExposeSecret;
+use uuid::Uuid;
+use zero2prod::configuration::{
+    ConfigurationError, Environment, find_config_dir, load_configuration,
+};
+
+pub const VALID_BASE_YAML: &str = r#"
+database:
+  name: newsletter
+  host: 127.0.0.1
+  port: 5432
+  user:
+    name: app
+    password: secret
+server:
+  host: 127.0.0.1
+  port: 8000
+api:
+  token: a-token
+"#;
+
+// A throwaway `configuration/` directory
+pub fn config_dir(base_yaml: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("zero2prod-config-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("base.yaml"), base_yaml).unwrap();
+    std::fs::write(dir.join("local.yaml"), "").unwrap();
+    dir
+}
+
+fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn a_valid_configuration_is_loaded_and_bootstrap_variables_are_not_settings() {
+    // ARRANGE
+    let dir = config_dir(VALID_BASE_YAML);
+
+    // ACT
+    // `APP_ENVIRONMENT` selects the YAML file: it is not an unknown `environment` key
+    let settings = load_configuration(
+        &dir,
+        &Environment::Local,
+        vars(&[("APP_ENVIRONMENT", "local"), ("APP_SERVER__PORT", "9000")]),
+    );
+
+    // ASSERT
+    let settings = settings.expect("The configuration was rejected");
+    assert_eq!(settings.server.port, 9000);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn every_problem_is_reported_with_the_source_of_its_value() {
+    // ARRANGE
+    let dir = config_dir(&VALID_BASE_YAML.replace("port: 8000", "port: 0\n  prot: 8000"));
+
+    // ACT
+    let error = load_configuration(
+        &dir,
+        &Environment::Local,
+        vars(&[("APP_DATABASE__PORT", "0"), ("APP_API__TOKEN", "")]),
+    )
+    .expect_err("The configuration was accepted");
+
+    // ASSERT
+    let ConfigurationError::Invalid(problems) = &error else {
+        panic!("Unexpected error: {}", error);
+    };
+    // File paths are relative to the current directory: only their name matters here
+    let mut reported: Vec<(&str, &str)> = problems
+        .iter()
+        .map(|p| {
+            let source = p.source.as_deref().unwrap_or("");
+            (p.key.as_str(), source.rsplit('/').next().unwrap())
+        })
+        .collect();
+    reported.sort();
+    assert_eq!(
+        reported,
+        vec![
+            ("api.token", "APP_API__TOKEN"),
+            ("database.port", "APP_DATABASE__PORT"),
+            ("server.port", "base.yaml"),
+            ("server.prot", "base.yaml"),
+        ]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn secrets_can_be_read_from_files_and_other_variables() {
+    // ARRANGE
+    let dir = config_dir("");
+    let password_file = dir.join("db_password");
+    std::fs::write(&password_file, "from-a-file\n").unwrap();
+    let base_yaml = VALID_BASE_YAML
+        .replace(
+            "password: secret",
+            &format!("password_file: {}", password_file.display()),
+        )
+        .replace("token: a-token", "token_env: NEWSLETTER_API_TOKEN");
+    std::fs::write(dir.join("base.yaml"), base_yaml).unwrap();
+
+    // ACT
+    let settings = load_configuration(
+        &dir,
+        &Environment::Local,
+        vars(&[("NEWSLETTER_API_TOKEN", "from-a-variable")]),
+    )
+    .expect("The configuration was rejected");
+
+    // ASSERT
+    assert_eq!(
+        settings.database.user.password.expose_secret(),
+        "from-a-file"
+    );
+    assert_eq!(settings.api.token.expose_secret(), "from-a-variable");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unresolvable_secret_references_are_reported() {
+    // ARRANGE
+    let dir = config_dir(
+        &VALID_BASE_YAML
+            .replace("password: secret", "password_file: /does/not/exist")
+            .replace("token: a-token", "token_env: NOT_SET"),
+    );
+
+    // ACT
+    let error = load_configuration(&dir, &Environment::Local, vars(&[]))
+        .expect_err("The configuration was accepted");
+
+    // ASSERT
+    let ConfigurationError::Invalid(problems) = &error else {
+        panic!("Unexpected error: {}", error);
+    };
+    let keys: Vec<&str> = problems.iter().map(|p| p.key.as_str()).collect();
+    assert_eq!(keys, vec!["database.user.password_file", "api.token_env"]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn outside_local_and_test_the_api_token_must_be_set_to_a_secret_one() {
+    // ARRANGE
+    let dir = config_dir(&VALID_BASE_YAML.replace("api:\n  token: a-token\n", ""));
+    std::fs::write(dir.join("staging.yaml"), "").unwrap();
+
+    for (token, env) in [
+        (None, Environment::Staging),
+        (Some("local-api-token"), Environment::Staging),
+    ] {
+        let env_vars = token.map(|token| ("APP_API__TOKEN", token));
+
+        // ACT
+        let error = load_configuration(&dir, &env, vars(env_vars.as_slice()))
+            .expect_err("The configuration was accepted");
+
+        // ASSERT
+        let ConfigurationError::Invalid(problems) = &error else {
+            panic!("Unexpected error: {}", error);
+        };
+        let keys: Vec<&str> = problems.iter().map(|p| p.key.as_str()).collect();
+        assert_eq!(keys, vec!["api.token"], "{:?}", token);
+    }
+    // Fine on a developer machine
+    let settings = load_configuration(
+        &dir,
+        &Environment::Local,
+        vars(&[("APP_API__TOKEN", "local-api-token")]),
+    );
+    assert!(settings.is_ok());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn the_first_directory_holding_a_base_yaml_is_the_configuration_directory() {
+    // ARRANGE
+    let dir = config_dir(VALID_BASE_YAML);
+    let empty = std::env::temp_dir().join(format!("zero2prod-empty-{}", Uuid::new_v4()));
+
+    // ACT
+    let found = find_config_dir(vec![empty.clone(), dir.clone()]);
+    let not_found = find_config_dir(vec![empty.clone()]);
+
+    // ASSERT
+    assert_eq!(found.expect("No directory was found"), dir);
+    let Err(ConfigurationError::NotFound { searched }) = &not_found else {
+        panic!("Unexpected outcome: {:?}", not_found);
+    };
+    assert_eq!(searched, &vec![empty.join("base.yaml")]);
+    // Every searched path is listed in the message
+    let message = not_found.unwrap_err().to_string();
+    assert!(message.contains(&empty.join("base.yaml").display().to_string()));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn a_missing_environment_file_is_reported_with_its_path() {
+    // ARRANGE
+    // `config_dir` only writes `base.yaml` and `local.yaml`
+    let dir = config_dir(VALID_BASE_YAML);
+
+    // ACT
+    let error = load_configuration(&dir, &Environment::Staging, vars(&[]))
+        .expect_err("The configuration was accepted");
+
+    // ASSERT
+    let ConfigurationError::NotFound { searched } = &error else {
+        panic!("Unexpected error: {}", error);
+    };
+    assert_eq!(searched, &vec![dir.join("staging.yaml")]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn the_server_host_may_be_a_list_of_ips_and_hostnames() {
+    // ARRANGE
+    let dir = config_dir(&VALID_BASE_YAML.replace(
+        "host: 127.0.0.1\n  port: 8000",
+        "host: [127.0.0.1, \"::1\", localhost]\n  port: 8000",
+    ));
+
+    // ACT
+    let settings = load_configuration(&dir, &Environment::Local, vars(&[]))
+        .expect("The configuration was rejected");
+    let addresses = settings.server.tcp_socket_addresses().unwrap();
+
+    // ASSERT
+    // `localhost` resolves to the addresses above: they are not bound twice
+    let addresses: Vec<String> = addresses.iter().map(|a| a.to_string()).collect();
+    assert_eq!(addresses[..2], ["127.0.0.1:8000", "[::1]:8000"]);
+    assert!(addresses.iter().all(|a| a.ends_with(":8000")));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unix_hosts_are_only_accepted_where_there_are_unix_sockets() {
+    // ARRANGE
+    let dir = config_dir(&VALID_BASE_YAML.replace(
+        "host: 127.0.0.1\n  port: 8000",
+        "host: [127.0.0.1, \"unix:/run/zero2prod/app.sock\"]\n  port: 8000",
+    ));
+
+    // ACT
+    let result = load_configuration(&dir, &Environment::Local, vars(&[]));
+
+    // ASSERT
+    if cfg!(unix) {
+        let settings = result.expect("The configuration was rejected");
+        assert_eq!(
+            settings.server.unix_socket_paths(),
+            vec![PathBuf::from("/run/zero2prod/app.sock")]
+        );
+    } else {
+        let error = result.expect_err("The configuration was accepted");
+        let ConfigurationError::Invalid(problems) = &error else {
+            panic!("Unexpected error: {}", error);
+        };
+        let reported: Vec<&str> = problems.iter().map(|p| p.key.as_str()).collect();
+        assert_eq!(reported, vec!["server.host"]);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn cors_origins_must_be_origins_and_credentials_need_named_ones() {
+    // ARRANGE
+    let base_yaml = format!(
+        "{}cors:\n  allowed_origins: ['*', 'https://app.example.com/', app.example.com]\n  allow_credentials: true\n",
+        VALID_BASE_YAML
+    );
+    let dir = config_dir(&base_yaml);
+
+    // ACT
+    let error = load_configuration(&dir, &Environment::Local, vec![])
+        .expect_err("The configuration was accepted");
+
+    // ASSERT
+    let ConfigurationError::Invalid(problems) = &error else {
+        panic!("Unexpected error: {}", error);
+    };
+    let reported: Vec<&str> = problems.iter().map(|p| p.key.as_str()).collect();
+    assert_eq!(
+        reported,
+        vec![
+            "cors.allowed_origins",
+            "cors.allowed_origins",
+            "cors.allow_credentials"
+        ]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
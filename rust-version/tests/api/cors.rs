@@ -0,0 +1,131 @@
+//! tests/api/cors.rs
+//! Cross-origin requests from the SPA to `/subscription` and `/api/v1`.
+
+use reqwest::Method;
+
+use crate::helpers::{TestApp, json_body, spawn_app, spawn_app_with};
+
+const SPA: &str = "https://app.example.com";
+
+async fn spawn_app_allowing_the_spa() -> TestApp {
+    spawn_app_with(|config| {
+        config.cors.allowed_origins = vec![SPA.to_string()];
+        config.cors.allowed_methods = vec!["GET".into(), "POST".into()];
+        config.cors.allow_credentials = true;
+    })
+    .await
+}
+
+async fn preflight(app: &TestApp, path: &str, origin: &str, method: &str) -> reqwest::Response {
+    app.api_client
+        .request(Method::OPTIONS, format!("{}{}", app.root_address, path))
+        .header("Origin", origin)
+        .header("Access-Control-Request-Method", method)
+        .header(
+            "Access-Control-Request-Headers",
+            "authorization, content-type",
+        )
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn preflights_of_allowed_origins_are_answered_without_a_token() {
+    // ARRANGE
+    let app = spawn_app_allowing_the_spa().await;
+
+    for path in ["/api/v1/subscribers", "/subscription"] {
+        // ACT
+        let response = preflight(&app, path, SPA, "POST").await;
+
+        // ASSERT
+        assert_eq!(response.status().as_u16(), 204, "{}", path);
+        let headers = response.headers();
+        assert_eq!(headers["Access-Control-Allow-Origin"], SPA);
+        assert_eq!(headers["Access-Control-Allow-Methods"], "GET, POST");
+        assert_eq!(
+            headers["Access-Control-Allow-Headers"],
+            "Authorization, Content-Type"
+        );
+        assert_eq!(headers["Access-Control-Allow-Credentials"], "true");
+        assert_eq!(headers["Access-Control-Max-Age"], "600");
+    }
+}
+
+#[tokio::test]
+async fn preflights_of_unknown_origins_or_methods_are_rejected() {
+    // ARRANGE
+    let app = spawn_app_allowing_the_spa().await;
+    let test_cases = vec![
+        ("https://evil.example.com", "POST", "origin"),
+        (SPA, "DELETE", "method"),
+    ];
+
+    for (origin, method, description) in test_cases {
+        // ACT
+        let response = preflight(&app, "/api/v1/subscribers", origin, method).await;
+
+        // ASSERT
+        assert_eq!(response.status().as_u16(), 403, "{}", description);
+        assert!(
+            !response
+                .headers()
+                .contains_key("Access-Control-Allow-Origin")
+        );
+        let problem = json_body(response).await;
+        assert_eq!(
+            problem["type"], "/problems/cors-rejected",
+            "{}",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn responses_are_readable_by_allowed_origins_only() {
+    // ARRANGE
+    let app = spawn_app_allowing_the_spa().await;
+    let request = |origin: &str| {
+        app.api_request(Method::GET, "/subscribers")
+            .header("Origin", origin)
+            .send()
+    };
+
+    // ACT
+    let allowed = request(SPA).await.unwrap();
+    let unknown = request("https://evil.example.com").await.unwrap();
+
+    // ASSERT
+    assert_eq!(allowed.status().as_u16(), 200);
+    assert_eq!(allowed.headers()["Access-Control-Allow-Origin"], SPA);
+    assert_eq!(
+        allowed.headers()["Access-Control-Expose-Headers"],
+        "Location, Retry-After"
+    );
+    assert_eq!(allowed.headers()["Vary"], "Origin");
+    // Served all the same: it is up to the browser to hide it from the page
+    assert_eq!(unknown.status().as_u16(), 200);
+    assert!(
+        !unknown
+            .headers()
+            .contains_key("Access-Control-Allow-Origin")
+    );
+}
+
+#[tokio::test]
+async fn without_allowed_origins_there_is_no_cors_at_all() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = preflight(&app, "/subscription", SPA, "POST").await;
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 405);
+    assert!(
+        !response
+            .headers()
+            .contains_key("Access-Control-Allow-Origin")
+    );
+}
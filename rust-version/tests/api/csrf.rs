@@ -0,0 +1,104 @@
+//! tests/api/csrf.rs
+//! Pages of other sites making an admin's browser call `/api/v1` with its cached password.
+
+use reqwest::Method;
+use zero2prod::authentication::Role;
+
+use crate::helpers::{Caller, json_body, spawn_app, spawn_app_with};
+
+const EVIL: &str = "https://evil.example.com";
+const SPA: &str = "https://app.example.com";
+
+#[tokio::test]
+async fn cross_site_writes_with_a_password_are_refused() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let editor = app.login("ed", Role::Editor).await;
+    let body = serde_json::json!({"email": "ursula@example.com", "name": "le guin"}).to_string();
+    // What a `<form enctype="text/plain">` or a `fetch` without preflight can send
+    let test_cases = vec![
+        (Method::POST, "/subscribers", "Origin", EVIL),
+        (Method::POST, "/subscribers", "Origin", "null"),
+        (Method::POST, "/subscribers", "Sec-Fetch-Site", "cross-site"),
+        (
+            Method::DELETE,
+            "/api-keys/00000000-0000-0000-0000-000000000000",
+            "Origin",
+            EVIL,
+        ),
+    ];
+
+    for (method, path, header, value) in test_cases {
+        // ACT
+        let response = app
+            .api_request_as(&editor, method.clone(), path)
+            .header(header, value)
+            .header("Content-Type", "text/plain")
+            .body(body.clone())
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // ASSERT
+        assert_eq!(
+            response.status().as_u16(),
+            403,
+            "{} {} {}",
+            method,
+            header,
+            value
+        );
+        assert_eq!(json_body(response).await["code"], "cross_site_request");
+    }
+    let saved: i64 = sqlx::query_scalar("SELECT count(*) FROM subscriptions")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved, 0);
+}
+
+#[tokio::test]
+async fn same_site_reads_trusted_origins_and_api_keys_are_let_through() {
+    // ARRANGE
+    let app = spawn_app_with(|config| {
+        config.cors.allowed_origins = vec![SPA.to_string()];
+        config.cors.allow_credentials = true;
+    })
+    .await;
+    let editor = app.login("ed", Role::Editor).await;
+    let api_token = Caller::Key(app.api_token.clone());
+    // Our own origin, as the browser writes it: the `Host` it sends, behind the scheme
+    let own_origin = app.root_address.clone();
+    let test_cases = vec![
+        (&editor, Method::POST, own_origin.as_str(), 201),
+        (&editor, Method::POST, SPA, 201),
+        // Reads change nothing: nothing to forge
+        (&editor, Method::GET, EVIL, 200),
+        // The browser never attaches a bearer token on its own
+        (&api_token, Method::POST, EVIL, 201),
+    ];
+
+    for (i, (caller, method, origin, status)) in test_cases.into_iter().enumerate() {
+        let subscriber =
+            serde_json::json!({"email": format!("ursula{}@example.com", i), "name": "le guin"});
+
+        // ACT
+        let response = app
+            .api_request_as(caller, method.clone(), "/subscribers")
+            .header("Origin", origin)
+            .header("Content-Type", "application/json")
+            .body(subscriber.to_string())
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // ASSERT
+        assert_eq!(
+            response.status().as_u16(),
+            status,
+            "{} from {}",
+            method,
+            origin
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! tests/api/email_policy.rs
+//! Role addresses, disposable domains and the operator's `/api/v1/email-domain-rules`.
+
+use reqwest::Method;
+
+use crate::helpers::{TestApp, json_body, spawn_app};
+
+async fn add_rule(app: &TestApp, domain: &str, kind: &str) -> reqwest::Response {
+    app.api_request(Method::POST, "/email-domain-rules")
+        .header("Content-Type", "application/json")
+        .body(
+            serde_json::json!({ "domain": domain, "kind": kind, "note": "from a test" })
+                .to_string(),
+        )
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+fn form(email: &str) -> String {
+    format!(
+        "name=le%20guin&email={}",
+        email.replace('@', "%40").replace('+', "%2B")
+    )
+}
+
+#[tokio::test]
+async fn refused_addresses_get_a_422_with_the_reason_as_code() {
+    // ARRANGE
+    let app = spawn_app().await;
+    assert_eq!(
+        add_rule(&app, "Spammy.Example.", "deny").await.status(),
+        201
+    );
+    let test_cases = vec![
+        ("postmaster@example.com", "email_role_address"),
+        ("Abuse+news@example.com", "email_role_address"),
+        ("ursula@mailinator.com", "email_domain_disposable"),
+        ("ursula@eu.yopmail.com", "email_domain_disposable"),
+        ("ursula@spammy.example", "email_domain_denied"),
+        ("ursula@mx.spammy.example", "email_domain_denied"),
+    ];
+
+    for (email, code) in test_cases {
+        // ACT
+        let response = app.post_subscriptions(form(email)).await;
+
+        // ASSERT
+        assert_eq!(response.status().as_u16(), 422, "{}", email);
+        let problem = json_body(response).await;
+        assert_eq!(problem["type"], "/problems/email-not-allowed", "{}", email);
+        assert_eq!(problem["code"], code, "{}", email);
+    }
+    // The management API enforces the same policy
+    let response = app
+        .create_subscriber(serde_json::json!({"email": "ursula@yopmail.com", "name": "le guin"}))
+        .await;
+    assert_eq!(response.status().as_u16(), 422);
+    assert_eq!(json_body(response).await["code"], "email_domain_disposable");
+    let saved: i64 = sqlx::query_scalar("SELECT count(*) FROM subscriptions")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved, 0);
+}
+
+#[tokio::test]
+async fn the_most_specific_rule_wins_over_the_disposable_list() {
+    // ARRANGE
+    let app = spawn_app().await;
+    add_rule(&app, "mailinator.com", "allow").await;
+    add_rule(&app, "eu.mailinator.com", "deny").await;
+
+    // ACT
+    let allowed = app.post_subscriptions(form("ursula@mailinator.com")).await;
+    let denied = app
+        .post_subscriptions(form("ursula@eu.mailinator.com"))
+        .await;
+
+    // ASSERT
+    assert_eq!(allowed.status().as_u16(), 200);
+    assert_eq!(json_body(denied).await["code"], "email_domain_denied");
+}
+
+#[tokio::test]
+async fn email_domain_rules_can_be_created_listed_updated_and_deleted() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let created = add_rule(&app, " Example.COM ", "deny").await;
+    let duplicate = add_rule(&app, "example.com", "allow").await;
+    let invalid = add_rule(&app, "ursula@example.com", "deny").await;
+    let updated = app
+        .api_request(Method::PUT, "/email-domain-rules/example.com")
+        .header("Content-Type", "application/json")
+        .body(serde_json::json!({ "kind": "allow", "note": null }).to_string())
+        .send()
+        .await
+        .unwrap();
+    let listed = app
+        .api_request(Method::GET, "/email-domain-rules")
+        .send()
+        .await
+        .unwrap();
+    let deleted = app
+        .api_request(Method::DELETE, "/email-domain-rules/example.com")
+        .send()
+        .await
+        .unwrap();
+    let gone = app
+        .api_request(Method::GET, "/email-domain-rules/example.com")
+        .send()
+        .await
+        .unwrap();
+
+    // ASSERT
+    assert_eq!(created.status().as_u16(), 201);
+    assert_eq!(
+        created.headers()["Location"],
+        "/api/v1/email-domain-rules/example.com"
+    );
+    assert_eq!(json_body(created).await["domain"], "example.com");
+    assert_eq!(
+        json_body(duplicate).await["code"],
+        "email_domain_rule_exists"
+    );
+    assert_eq!(json_body(invalid).await["code"], "invalid_domain");
+    let updated = json_body(updated).await;
+    assert_eq!(updated["kind"], "allow");
+    assert_eq!(updated["note"], serde_json::Value::Null);
+    let listed = json_body(listed).await;
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    assert_eq!(listed[0]["kind"], "allow");
+    assert_eq!(deleted.status().as_u16(), 204);
+    assert_eq!(json_body(gone).await["code"], "email_domain_rule_not_found");
+}
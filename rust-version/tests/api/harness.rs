@@ -0,0 +1,53 @@
+//! tests/api/harness.rs
+//! The test harness itself: what `helpers` reads off the emails the app would send.
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn confirmation_links_are_read_off_the_emails_received_by_the_mock_server() {
+    // ARRANGE
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // The link as the app would write it: without the random port of the test server
+    let mut link = reqwest::Url::parse(&app.root_address).unwrap();
+    link.set_port(None).unwrap();
+    link.set_path("/subscriptions/confirm");
+    link.set_query(Some("subscription_token=mytoken"));
+    // No email client yet: the test sends the delivery API request in its place
+    // (NOTE: not through `api_client`, which may be bound to a Unix socket)
+    reqwest::Client::new()
+        .post(format!("{}/email", app.email_server.uri()))
+        .header("Content-Type", "application/json")
+        .body(
+            serde_json::json!({
+                "HtmlBody": format!(r#"Welcome! <a href="{}">Confirm</a>"#, link),
+                "TextBody": format!("Welcome! Visit {} to confirm.", link),
+            })
+            .to_string(),
+        )
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ACT
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let links = app.get_confirmation_links(email_request);
+
+    // ASSERT
+    assert_eq!(links.html, links.plain_text);
+    assert_eq!(
+        links.html.as_str(),
+        format!(
+            "{}/subscriptions/confirm?subscription_token=mytoken",
+            app.root_address
+        )
+    );
+}
@@ -0,0 +1,470 @@
+//! tests/api/helpers.rs
+//! Test harness shared by every integration test: spawns the app against its own database.
+
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock};
+use uuid::Uuid;
+
+use notify::RecommendedWatcher;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+use wiremock::MockServer;
+
+use zero2prod::authentication::Role;
+use zero2prod::cli;
+use zero2prod::configuration::{
+    DBUser, DatabaseSettings, Environment, Settings, config_dir, load_configuration,
+};
+use zero2prod::listener::{Listener, bind_unix_sockets};
+use zero2prod::migrations::run_migrations;
+use zero2prod::rate_limit::RateLimiter;
+use zero2prod::reload::LiveSettings;
+use zero2prod::telemetry::{LogFormat, get_subscriber, init_subscriber};
+use zero2prod::tls::ReloadableCertificate;
+
+// Ensure that the `tracing` stack is only initialised once using `LazyLock`
+// LazyLock provides thread-safe lazy initialization:
+// - static: global variable with 'static lifetime (lives entire program duration)
+// - LazyLock: the closure runs EXACTLY ONCE on first access, even with concurrent threads
+// - Thread-safe: uses atomic operations, subsequent accesses skip initialization
+// Why needed: init_subscriber() panics if called twice, but each test runs in its own thread
+// Scala equivalent: lazy val (but LazyLock is lock-free after init, lazy val uses synchronized)
+//
+// - Memory location: Stored in the binary's data segment (not on stack or heap)
+// - Shared across threads: All threads see the same instance
+//
+// Contrast with:
+// - Local variables: live on the stack, destroyed when function returns
+// - Heap allocations: live until explicitly freed
+// - const: compile-time constant, gets inlined (no memory address)
+static TRACING: LazyLock<()> = LazyLock::new(|| {
+    // Choose the sink based on TEST_LOG environment variable:
+    // - If TEST_LOG is set: output logs to stdout
+    // - If TEST_LOG is not set: discard all logs (sink to avoid test noise)
+    //
+    // Usage: TEST_LOG=true cargo test --test api health_check_works | bunyan
+    //
+    // NOTE: This looks duplicated, but Rust's `impl Trait` returns different opaque types
+    // for each call to get_subscriber() with different sink types (stdout vs sink).
+    // We cannot do:
+    //   let subscriber = if TEST_LOG { get_subscriber(..., stdout) } else { get_subscriber(..., sink) }
+    // because the if-else branches would have incompatible types (different opaque impl Trait).
+    //
+    // We also cannot do:
+    //   let sink = if TEST_LOG { stdout() } else { sink() }
+    // because stdout() returns Stdout, sink() returns Sink - different concrete types.
+    //
+    // Therefore we must duplicate the get_subscriber + init_subscriber calls in each branch.
+    let subscriber_name = "test".into();
+    let subscriber_env = "debug".into();
+
+    if std::env::var("TEST_LOG").is_ok() {
+        let subscriber = get_subscriber(
+            subscriber_name,
+            subscriber_env,
+            LogFormat::Bunyan,
+            std::io::stdout,
+        );
+        init_subscriber(subscriber);
+    } else {
+        let subscriber = get_subscriber(
+            subscriber_name,
+            subscriber_env,
+            LogFormat::Bunyan,
+            std::io::sink,
+        );
+        init_subscriber(subscriber);
+    };
+});
+
+/// Who calls `/api/v1`: an admin (HTTP Basic, see `TestApp::login`) or an API key (Bearer).
+#[derive(Debug, Clone)]
+pub enum Caller {
+    Admin { username: String, password: String },
+    Key(String),
+}
+
+/// The links of a confirmation email, pointing at the app under test.
+pub struct ConfirmationLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+}
+
+/// A running instance of the app, backed by its own freshly migrated database.
+///
+/// The database is DROPPED when the `TestApp` goes out of scope
+/// (set `TEST_KEEP_DB` to keep it around for a post-mortem).
+pub struct TestApp {
+    // nota: the http:// scheme is already baked in
+    pub root_address: String,
+    // One per TCP listener (`root_address` being the first one), e.g. `http://[::1]:41234`
+    // (`https://` when `server.tls` is set)
+    pub root_addresses: Vec<String>,
+    // The Unix socket listeners: `api_client` goes through the first one when there is no TCP one
+    pub unix_sockets: Vec<PathBuf>,
+    // The plain HTTP listener redirecting to HTTPS, when `server.tls.redirect_port` is set
+    pub redirect_address: Option<String>,
+    // Reloads the certificate on change, as long as the app lives
+    pub _certificate_watcher: Option<RecommendedWatcher>,
+    pub db_conn_pool: PgPool,
+    pub db_settings: DatabaseSettings,
+    pub api_token: String,
+    // Stands in for the email delivery API: assert on the requests it received
+    pub email_server: MockServer,
+    pub api_client: reqwest::Client,
+    // What the app reads its reloadable settings from
+    pub live_settings: Arc<LiveSettings>,
+}
+
+impl TestApp {
+    pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
+        self.post_subscriptions_as(body, "application/x-www-form-urlencoded")
+            .await
+    }
+
+    pub async fn post_subscriptions_as(
+        &self,
+        body: String,
+        content_type: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscription", &self.root_address))
+            .header("Content-Type", content_type)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Authenticated request builder for the `/api/v1` endpoints
+    pub fn api_request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.api_request_as(&Caller::Key(self.api_token.clone()), method, path)
+    }
+
+    /// Same, with the credentials of `caller` instead of the configured token
+    pub fn api_request_as(
+        &self,
+        caller: &Caller,
+        method: reqwest::Method,
+        path: &str,
+    ) -> reqwest::RequestBuilder {
+        let request = self
+            .api_client
+            .request(method, format!("{}/api/v1{}", self.root_address, path));
+        match caller {
+            Caller::Admin { username, password } => request.basic_auth(username, Some(password)),
+            Caller::Key(key) => request.bearer_auth(key),
+        }
+    }
+
+    /// A new admin with `role`, created as `zero2prod create-admin` does: its password is
+    /// the one printed (once) by the command.
+    pub async fn login(&self, username: &str, role: Role) -> Caller {
+        let mut out = vec![];
+        cli::create_admin(&self.db_conn_pool, username, role, &mut out)
+            .await
+            .expect("Failed to create the admin");
+        let password = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .find_map(|line| line.strip_prefix("Password (shown only once): "))
+            .expect("The password was not printed")
+            .to_string();
+        Caller::Admin {
+            username: username.to_string(),
+            password,
+        }
+    }
+
+    /// The confirmation links of an email sent through `email_server`
+    /// (a delivery API request, with an `HtmlBody` and a `TextBody`).
+    pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body)
+            .expect("The email request is not valid JSON");
+        let get_link = |text: &str| {
+            let links: Vec<&str> = text
+                .split(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>'))
+                .filter(|word| word.starts_with("http://") || word.starts_with("https://"))
+                .collect();
+            assert_eq!(links.len(), 1, "Expected a single link in {:?}", text);
+            let mut link = reqwest::Url::parse(links[0]).expect("Invalid confirmation link");
+            // Never follow a link out of the app under test: same scheme, host and (random) port
+            let root = reqwest::Url::parse(&self.root_address).unwrap();
+            assert_eq!(link.host_str(), root.host_str(), "{} leaves the app", link);
+            link.set_scheme(root.scheme()).unwrap();
+            link.set_port(root.port()).unwrap();
+            link
+        };
+        ConfirmationLinks {
+            html: get_link(body["HtmlBody"].as_str().expect("No HtmlBody")),
+            plain_text: get_link(body["TextBody"].as_str().expect("No TextBody")),
+        }
+    }
+
+    pub async fn create_subscriber(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_request(reqwest::Method::POST, "/subscribers")
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+}
+
+// TEARDOWN
+// `Drop` cannot be async, and we cannot block the test's runtime from within it either:
+// the database is dropped from a separate thread, with its own single-threaded runtime.
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        for path in &self.unix_sockets {
+            let _ = std::fs::remove_file(path);
+        }
+        teardown(&self.db_settings);
+    }
+}
+
+/// A freshly created database, left EMPTY (no migration applied): dropped with the guard.
+pub struct TestDatabase {
+    pub settings: DatabaseSettings,
+    pub db_conn_pool: PgPool,
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        teardown(&self.settings);
+    }
+}
+
+fn teardown(db_settings: &DatabaseSettings) {
+    if std::env::var("TEST_KEEP_DB").is_ok() {
+        return;
+    }
+    let db_conf = db_settings.clone();
+    let teardown = std::thread::spawn(move || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to build the teardown runtime")
+            .block_on(drop_database(&db_conf))
+    });
+    // NOTE: no panic here, a panic while already panicking (failed test) aborts the whole run.
+    match teardown.join() {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => eprintln!("Failed to drop test db {}: {}", db_settings.name, e),
+        Err(_) => eprintln!("Failed to drop test db {}", db_settings.name),
+    }
+}
+
+pub async fn json_body(response: reqwest::Response) -> serde_json::Value {
+    serde_json::from_str(&response.text().await.unwrap()).expect("The body is not valid JSON")
+}
+
+/// The `test` environment (`configuration/test.yaml`), whatever `APP_ENVIRONMENT` says.
+/// `config_dir` walks up from the current directory: `cargo test` works from any subdirectory.
+pub fn test_configuration() -> Settings {
+    let config_dir = config_dir().expect("Failed to find the configuration directory");
+    load_configuration(&config_dir, &Environment::Test, std::env::vars())
+        .expect("Failed to read config")
+}
+
+// We are running tests, so it is not worth it to propagate errors:
+// if we fail to perform the required setup we can just panic and crash.
+pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+// `customise`: tweaks the test configuration before the app starts, e.g. its `server.host`
+pub async fn spawn_app_with(customise: impl FnOnce(&mut Settings)) -> TestApp {
+    // The first time `initialize` is invoked the code in `TRACING` is executed.
+    // All other invocations will instead skip execution.
+    LazyLock::force(&TRACING);
+
+    // WARNING: In order to achieve 'test isolation' & determinism
+    // Before each test run, we want to:
+    //  - create a new db with a random, unique name
+    //  - run database migration
+    let mut config: Settings = test_configuration();
+    // `TEST_TRANSPORT=unix cargo test --test api`: the whole suite, over a Unix socket
+    if std::env::var("TEST_TRANSPORT").as_deref() == Ok("unix") {
+        config.server.host = vec![format!("unix:{}", unique_socket_path().display())];
+    }
+    customise(&mut config);
+    config.database.name = Uuid::new_v4().to_string();
+    let db_conn_pool = configure_database(&config.database).await;
+
+    let email_server = MockServer::start().await;
+
+    let listeners = bind(
+        config
+            .server
+            .with_random_port()
+            .expect("Failed to resolve the server host"),
+    );
+    // We retrieve the addresses (i.e. the ports) assigned to us by the OS
+    // NOTE: `SocketAddr` displays IPv6 in brackets, as URLs want them (`[::1]:41234`)
+    let scheme = if config.server.tls.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    let root_addresses: Vec<String> = listeners
+        .iter()
+        .map(|listener| format!("{}://{}", scheme, listener.local_addr().unwrap()))
+        .collect();
+    let unix_sockets = config.server.unix_socket_paths();
+    let unix_listeners = bind_unix_sockets(&unix_sockets).expect("Failed to bind the Unix socket");
+
+    let mut tls = None;
+    let mut certificate_watcher = None;
+    let mut redirect_address = None;
+    if let Some(tls_settings) = &config.server.tls {
+        let certificate =
+            Arc::new(ReloadableCertificate::load(tls_settings).expect("Failed to load the cert"));
+        tls = Some(certificate.server_config().unwrap());
+        certificate_watcher = Some(certificate.watch().expect("Failed to watch the cert"));
+        if tls_settings.redirect_port.is_some() {
+            let redirect_listeners = bind(config.server.with_random_port().unwrap());
+            let https_port = listeners[0].local_addr().unwrap().port();
+            redirect_address = Some(format!(
+                "http://{}",
+                redirect_listeners[0].local_addr().unwrap()
+            ));
+            let redirect = zero2prod::startup::run_redirect(redirect_listeners, https_port)
+                .expect("Failed to bind address");
+            drop(tokio::spawn(redirect));
+        }
+    }
+
+    let live_settings = Arc::new(LiveSettings::new(&config));
+    let listeners = listeners
+        .into_iter()
+        .map(Listener::Tcp)
+        .chain(unix_listeners)
+        .collect();
+    let rate_limiter = RateLimiter::new(config.rate_limit.store, &db_conn_pool);
+    let server = zero2prod::startup::run(
+        listeners,
+        db_conn_pool.clone(),
+        live_settings.clone(),
+        tls,
+        rate_limiter,
+    )
+    .expect("Failed to bind address"); // Launch the server as a background task
+    // tokio::spawn returns a handle to the spawned future,
+    // but we have no use for it here, hence we explicitly drop it (the task keeps running)
+    drop(tokio::spawn(server));
+
+    // Over a Unix socket, the host of the URLs is only used for the `Host` header
+    let (root_address, api_client) = match (root_addresses.first(), unix_sockets.first()) {
+        (Some(root_address), _) => (root_address.clone(), reqwest::Client::new()),
+        #[cfg(unix)]
+        (None, Some(path)) => (
+            "http://localhost".to_string(),
+            reqwest::Client::builder()
+                .unix_socket(path.clone())
+                .build()
+                .unwrap(),
+        ),
+        _ => panic!("The app does not listen anywhere"),
+    };
+    TestApp {
+        root_address,
+        root_addresses,
+        unix_sockets,
+        redirect_address,
+        _certificate_watcher: certificate_watcher,
+        db_conn_pool,
+        db_settings: config.database.clone(),
+        api_token: config.api.token.expose_secret().clone(),
+        email_server,
+        api_client,
+        live_settings,
+    }
+}
+
+fn bind(addresses: Vec<SocketAddr>) -> Vec<TcpListener> {
+    addresses
+        .iter()
+        .map(|address| {
+            TcpListener::bind(address)
+                .unwrap_or_else(|_| panic!("Failed to bind to the address {}", address))
+        })
+        .collect()
+}
+
+// Unique per test, just like the random ports (NOTE: a socket path is at most ~100 bytes long)
+pub fn unique_socket_path() -> PathBuf {
+    std::env::temp_dir().join(format!("zero2prod-{}.sock", Uuid::new_v4()))
+}
+
+pub async fn spawn_database() -> TestDatabase {
+    let mut settings = test_configuration().database;
+    settings.name = Uuid::new_v4().to_string();
+    let db_conn_pool = create_database(&settings).await;
+    TestDatabase {
+        settings,
+        db_conn_pool,
+    }
+}
+
+// The `postgres` database always exists: we connect to it to create/drop the test ones.
+fn maintenance_settings(db_conf: &DatabaseSettings) -> DatabaseSettings {
+    DatabaseSettings {
+        name: "postgres".to_string(),
+        user: DBUser {
+            name: "postgres".to_string(),
+            password: Secret::new("password".to_string()),
+        },
+        // Struct update syntax: every other field is taken from `db_conf.clone()`
+        ..db_conf.clone()
+    }
+}
+
+pub async fn configure_database(db_conf: &DatabaseSettings) -> PgPool {
+    let db_conn_pool = create_database(db_conf).await;
+    // Same embedded migrations as `zero2prod migrate`
+    run_migrations(&db_conn_pool)
+        .await
+        .expect("Failed to migrate test db");
+    db_conn_pool
+}
+
+async fn create_database(db_conf: &DatabaseSettings) -> PgPool {
+    let mut db_conn = PgConnection::connect(
+        maintenance_settings(db_conf)
+            .connection_string()
+            .expose_secret(),
+    )
+    .await
+    .expect("Failed to connect to maintenance postgres instance");
+
+    db_conn
+        // r#"..."#: a raw string, no need to escape the double quotes inside
+        .execute(format!(r#"CREATE DATABASE "{}";"#, db_conf.name).as_str())
+        .await
+        .expect("Failed to create test db");
+
+    PgPool::connect_lazy(db_conf.clone().connection_string().expose_secret())
+        .expect("Failed to create pool for test db")
+}
+
+async fn drop_database(db_conf: &DatabaseSettings) -> Result<(), sqlx::Error> {
+    let mut db_conn = PgConnection::connect(
+        maintenance_settings(db_conf)
+            .connection_string()
+            .expose_secret(),
+    )
+    .await?;
+    // WITH (FORCE): the server under test may still hold connections to it (Postgres 13+)
+    db_conn
+        .execute(
+            format!(
+                r#"DROP DATABASE IF EXISTS "{}" WITH (FORCE);"#,
+                db_conf.name
+            )
+            .as_str(),
+        )
+        .await?;
+    Ok(())
+}
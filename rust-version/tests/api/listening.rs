@@ -0,0 +1,98 @@
+//! tests/api/listening.rs
+//! Binding the configured `server.host` addresses: IPv4, IPv6 and Unix sockets alike.
+
+#[cfg(unix)]
+use std::io::ErrorKind;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+#[cfg(unix)]
+use zero2prod::listener::{UNIX_SOCKET_MODE, bind_unix_socket};
+
+use crate::helpers::spawn_app_with;
+#[cfg(unix)]
+use crate::helpers::unique_socket_path;
+
+#[tokio::test]
+async fn the_app_answers_on_every_configured_address() {
+    // ARRANGE
+    let app = spawn_app_with(|config| {
+        config.server.host = vec!["127.0.0.1".to_string(), "::1".to_string()];
+    })
+    .await;
+
+    // ACT
+    let mut statuses = vec![];
+    for root_address in &app.root_addresses {
+        let response = app
+            .api_client
+            .get(format!("{}/health_check", root_address))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        statuses.push((root_address.clone(), response.status().as_u16()));
+    }
+
+    // ASSERT
+    assert_eq!(app.root_addresses.len(), 2);
+    assert!(app.root_addresses[0].starts_with("http://127.0.0.1:"));
+    assert!(app.root_addresses[1].starts_with("http://[::1]:"));
+    for (root_address, status) in statuses {
+        assert_eq!(status, 200, "No answer on {}", root_address);
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn the_app_answers_on_a_unix_socket_reserved_to_its_owner_and_group() {
+    // ARRANGE
+    let path = unique_socket_path();
+    let host = format!("unix:{}", path.display());
+    let app = spawn_app_with(|config| config.server.host = vec![host]).await;
+
+    // ACT
+    // `api_client` connects through the socket
+    let response = app
+        .api_client
+        .get(format!("{}/health_check", app.root_address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 200);
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, UNIX_SOCKET_MODE);
+}
+
+#[cfg(unix)]
+#[test]
+fn a_stale_socket_is_replaced_but_a_live_one_or_a_regular_file_is_not() {
+    // ARRANGE
+    let path = unique_socket_path();
+    // Left behind by a process which did not clean up after itself
+    drop(UnixListener::bind(&path).unwrap());
+    let regular_file = unique_socket_path();
+    std::fs::write(&regular_file, "not a socket").unwrap();
+
+    // ACT
+    let replaced = bind_unix_socket(&path);
+    let in_use = bind_unix_socket(&path);
+    let not_a_socket = bind_unix_socket(&regular_file);
+
+    // ASSERT
+    assert!(replaced.is_ok(), "{:?}", replaced.err());
+    assert_eq!(in_use.err().map(|e| e.kind()), Some(ErrorKind::AddrInUse));
+    assert_eq!(
+        not_a_socket.err().map(|e| e.kind()),
+        Some(ErrorKind::AlreadyExists)
+    );
+    assert_eq!(
+        std::fs::read_to_string(&regular_file).unwrap(),
+        "not a socket"
+    );
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(regular_file).unwrap();
+}
@@ -0,0 +1,28 @@
+//! tests/api/main.rs
+//!
+//! Rust-specific integration tests, one module per area of the app.
+//! A single test binary (instead of one per file) means `helpers` is compiled once
+//! and every test shares it: `cargo test --test api`.
+//!
+//! Behaviour every backend must share belongs to `tests/conformance` instead.
+
+mod api_keys;
+mod bot_protection;
+mod cli;
+mod configuration;
+mod cors;
+mod csrf;
+mod email_policy;
+mod harness;
+mod helpers;
+mod listening;
+mod migrations;
+mod openapi;
+mod problem_details;
+mod rate_limit;
+mod reload;
+mod roles;
+mod security_headers;
+mod subscribers;
+mod subscriptions;
+mod tls;
@@ -0,0 +1,134 @@
+//! tests/api/migrations.rs
+//! The migrations embedded in the binary (`zero2prod migrate`, `database.migrate_on_startup`).
+
+use zero2prod::migrations::{MIGRATOR, pending_migrations, run_migrations};
+
+use crate::helpers::spawn_database;
+
+#[tokio::test]
+async fn dry_run_lists_every_migration_of_a_new_database_without_applying_them() {
+    // ARRANGE
+    let db = spawn_database().await;
+
+    // ACT
+    let first = pending_migrations(&db.db_conn_pool).await.unwrap();
+    let second = pending_migrations(&db.db_conn_pool).await.unwrap();
+
+    // ASSERT
+    let embedded: Vec<i64> = MIGRATOR.iter().map(|m| m.version).collect();
+    let pending: Vec<i64> = first.iter().map(|m| m.version).collect();
+    assert_eq!(pending, embedded);
+    assert_eq!(second.len(), first.len(), "The dry run applied something");
+}
+
+#[tokio::test]
+async fn concurrent_replicas_migrate_the_database_exactly_once() {
+    // ARRANGE
+    // One pool per "replica": they do not share any connection
+    let db = spawn_database().await;
+    let replica = || sqlx::PgPool::connect_lazy_with((*db.db_conn_pool.connect_options()).clone());
+    let (a, b, c) = (replica(), replica(), replica());
+
+    // ACT
+    // `join!` polls the three futures concurrently (on the same task)
+    let (ra, rb, rc) = tokio::join!(run_migrations(&a), run_migrations(&b), run_migrations(&c));
+    let results = [ra, rb, rc];
+
+    // ASSERT
+    for result in results {
+        assert!(result.is_ok(), "A replica failed to migrate: {:?}", result);
+    }
+    assert!(
+        pending_migrations(&db.db_conn_pool)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    let applied: i64 = sqlx::query_scalar("SELECT count(*) FROM _sqlx_migrations")
+        .fetch_one(&db.db_conn_pool)
+        .await
+        .unwrap();
+    assert_eq!(applied as usize, MIGRATOR.iter().count());
+}
+
+#[tokio::test]
+async fn emails_colliding_once_case_is_ignored_are_deduplicated_and_reported() {
+    // ARRANGE
+    let db = spawn_database().await;
+    // Every migration up to the one making emails case-insensitive (excluded)
+    let dedup = MIGRATOR
+        .iter()
+        .find(|m| m.description == "make subscriber emails case insensitive")
+        .expect("The deduplicating migration");
+    for migration in MIGRATOR.iter().filter(|m| m.version < dedup.version) {
+        sqlx::raw_sql(&migration.sql)
+            .execute(&db.db_conn_pool)
+            .await
+            .unwrap();
+    }
+    sqlx::raw_sql(
+        r#"
+        INSERT INTO subscriptions(id, email, name, subscribed_at, status) VALUES
+            ('00000000-0000-0000-0000-000000000001', 'ursula@gmail.com', 'first', '2026-01-01', 'pending_confirmation'),
+            ('00000000-0000-0000-0000-000000000002', 'Ursula@Gmail.COM', 'confirmed', '2026-02-01', 'confirmed'),
+            ('00000000-0000-0000-0000-000000000003', ' URSULA@gmail.com', 'third', '2026-03-01', 'pending_confirmation'),
+            ('00000000-0000-0000-0000-000000000004', 'octavia@Example.com', 'alone', '2026-01-01', 'confirmed'),
+            ('00000000-0000-0000-0000-000000000005', 'le.guin@Example.org', 'domain-case-older', '2026-01-01', 'pending_confirmation'),
+            ('00000000-0000-0000-0000-000000000006', 'le.guin@example.org', 'domain-case-newer', '2026-02-01', 'pending_confirmation'),
+            ('00000000-0000-0000-0000-000000000007', ' butler@example.net', 'whitespace-older', '2026-01-01', 'pending_confirmation'),
+            ('00000000-0000-0000-0000-000000000008', 'butler@example.net', 'whitespace-newer', '2026-02-01', 'pending_confirmation');
+        "#,
+    )
+    .execute(&db.db_conn_pool)
+    .await
+    .unwrap();
+
+    // ACT
+    sqlx::raw_sql(&dedup.sql)
+        .execute(&db.db_conn_pool)
+        .await
+        .unwrap();
+
+    // ASSERT
+    let kept: Vec<(String, String)> =
+        sqlx::query_as("SELECT name, email FROM subscriptions ORDER BY name")
+            .fetch_all(&db.db_conn_pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        kept,
+        vec![
+            ("alone".to_string(), "octavia@example.com".to_string()),
+            ("confirmed".to_string(), "Ursula@gmail.com".to_string()),
+            // Identical once canonical: only the domain case, or whitespace, told them apart
+            (
+                "domain-case-older".to_string(),
+                "le.guin@example.org".to_string()
+            ),
+            (
+                "whitespace-older".to_string(),
+                "butler@example.net".to_string()
+            ),
+        ]
+    );
+    let reported: Vec<(String, uuid::Uuid)> =
+        sqlx::query_as("SELECT name, kept_id FROM subscription_email_duplicates ORDER BY name")
+            .fetch_all(&db.db_conn_pool)
+            .await
+            .unwrap();
+    let confirmed = uuid::Uuid::from_u128(2);
+    assert_eq!(
+        reported,
+        vec![
+            ("domain-case-newer".to_string(), uuid::Uuid::from_u128(5)),
+            ("first".to_string(), confirmed),
+            ("third".to_string(), confirmed),
+            ("whitespace-newer".to_string(), uuid::Uuid::from_u128(7)),
+        ]
+    );
+    // From now on, case-insensitive uniqueness
+    let duplicate = sqlx::query("INSERT INTO subscriptions VALUES (gen_random_uuid(), 'OCTAVIA@example.com', 'x', now(), 'confirmed')")
+        .execute(&db.db_conn_pool)
+        .await;
+    assert!(duplicate.is_err());
+}
@@ -0,0 +1,23 @@
+//! tests/api/openapi.rs
+
+use crate::helpers::{json_body, spawn_app};
+
+#[tokio::test]
+async fn openapi_document_is_served() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let response = app
+        .api_client
+        .get(format!("{}/openapi.json", app.root_address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    assert_eq!(200, response.status().as_u16());
+    let document = json_body(response).await;
+    assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+    assert!(document["paths"]["/subscription"]["post"].is_object());
+}
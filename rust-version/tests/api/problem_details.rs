@@ -0,0 +1,62 @@
+//! tests/api/problem_details.rs
+
+use crate::helpers::{json_body, spawn_app};
+
+#[tokio::test]
+async fn error_responses_are_rendered_as_problem_details() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let client = &app.api_client;
+    let test_cases = vec![
+        (
+            client
+                .post(format!("{}/subscription", app.root_address))
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .body("name=le%20guin"),
+            400,
+            "an extractor failure",
+        ),
+        (
+            client.get(format!("{}/subscription", app.root_address)),
+            405,
+            "a method not allowed",
+        ),
+        (
+            client.get(format!("{}/this-route-does-not-exist", app.root_address)),
+            404,
+            "an unknown route",
+        ),
+        (
+            client
+                .post(format!("{}/subscription", app.root_address))
+                .header("Content-Type", "application/json")
+                .body(format!(r#"{{"name": "{}"}}"#, "a".repeat(300_000))),
+            413,
+            "a payload too large",
+        ),
+    ];
+
+    for (request, expected_status, description) in test_cases {
+        // ACT
+        let response = request.send().await.expect("Failed to execute request.");
+
+        // ASSERT
+        assert_eq!(
+            expected_status,
+            response.status().as_u16(),
+            "{}",
+            description
+        );
+        assert_eq!(
+            "application/problem+json",
+            response.headers()["Content-Type"],
+            "{} was not rendered as a problem",
+            description
+        );
+        let problem = json_body(response).await;
+        assert_eq!(problem["status"], expected_status, "{}", description);
+        assert!(problem["type"].is_string(), "{}", description);
+        assert!(problem["title"].is_string(), "{}", description);
+        assert!(problem["request_id"].is_string(), "{}", description);
+    }
+}
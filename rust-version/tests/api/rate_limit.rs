@@ -0,0 +1,109 @@
+//! tests/api/rate_limit.rs
+//! `POST /subscription` is rate limited by client IP and by email address.
+
+use zero2prod::configuration::{BucketSettings, RateLimitStore};
+use zero2prod::rate_limit::{Decision, RateLimiter};
+
+use crate::helpers::{json_body, spawn_app_with};
+
+const GENEROUS: BucketSettings = BucketSettings {
+    burst: 1000,
+    per_hour: 1000,
+};
+
+#[tokio::test]
+async fn too_many_subscriptions_from_one_ip_are_rejected_with_a_retry_after() {
+    // ARRANGE
+    let app = spawn_app_with(|config| {
+        // TCP, whatever `TEST_TRANSPORT` says: there is no client IP over a Unix socket
+        config.server.host = vec!["127.0.0.1".to_string()];
+        config.rate_limit.per_ip = BucketSettings {
+            burst: 2,
+            per_hour: 60,
+        };
+        config.rate_limit.per_email = GENEROUS;
+    })
+    .await;
+
+    // ACT
+    let mut statuses = vec![];
+    for i in 0..3 {
+        let body = format!("name=le%20guin&email=ursula{}%40example.com", i);
+        statuses.push(app.post_subscriptions(body).await);
+    }
+
+    // ASSERT
+    let rejected = statuses.pop().unwrap();
+    for accepted in statuses {
+        assert_eq!(accepted.status().as_u16(), 200);
+    }
+    assert_eq!(rejected.status().as_u16(), 429);
+    // 60 per hour: a token every minute
+    let retry_after: u64 = rejected.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after), "{}", retry_after);
+    let problem = json_body(rejected).await;
+    assert_eq!(problem["type"], "/problems/rate-limited");
+    // The rejected request never reached the handler
+    let saved: i64 = sqlx::query_scalar("SELECT count(*) FROM subscriptions")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved, 2);
+}
+
+#[tokio::test]
+async fn too_many_subscriptions_of_one_email_are_rejected_whatever_its_case() {
+    // ARRANGE
+    let app = spawn_app_with(|config| {
+        config.rate_limit.per_ip = GENEROUS;
+        config.rate_limit.per_email = BucketSettings {
+            burst: 1,
+            per_hour: 1,
+        };
+    })
+    .await;
+
+    // ACT
+    let first = app
+        .post_subscriptions("name=le%20guin&email=ursula%40example.com".into())
+        .await;
+    let second = app
+        .post_subscriptions_as(
+            r#"{"name": "le guin", "email": " Ursula@Example.COM"}"#.into(),
+            "application/json",
+        )
+        .await;
+
+    // ASSERT
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn the_postgres_store_is_shared_by_every_replica() {
+    // ARRANGE
+    let app = spawn_app_with(|_| {}).await;
+    // Two replicas, one database
+    let replica_a = RateLimiter::new(RateLimitStore::Postgres, &app.db_conn_pool);
+    let replica_b = RateLimiter::new(RateLimitStore::Postgres, &app.db_conn_pool);
+    let limit = BucketSettings {
+        burst: 2,
+        per_hour: 1,
+    };
+
+    // ACT
+    let a = replica_a.check("ip:203.0.113.7", &limit).await.unwrap();
+    let b = replica_b.check("ip:203.0.113.7", &limit).await.unwrap();
+    let a_again = replica_a.check("ip:203.0.113.7", &limit).await.unwrap();
+    let other_key = replica_b.check("ip:203.0.113.8", &limit).await.unwrap();
+
+    // ASSERT
+    assert_eq!(a, Decision::Allowed);
+    assert_eq!(b, Decision::Allowed);
+    assert!(matches!(a_again, Decision::Limited { .. }));
+    assert_eq!(other_key, Decision::Allowed);
+}
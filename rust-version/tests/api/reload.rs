@@ -0,0 +1,85 @@
+//! tests/api/reload.rs
+//! Configuration hot reload: the reloadable settings change under a running app.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use secrecy::{ExposeSecret, Secret};
+use zero2prod::configuration::{ConfigurationError, Environment, load_configuration};
+use zero2prod::reload::{ConfigReloader, LiveSettings};
+
+use crate::configuration::{VALID_BASE_YAML, config_dir};
+use crate::helpers::{spawn_app, test_configuration};
+
+#[tokio::test]
+async fn a_rotated_api_token_is_used_without_a_restart() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let reloader = ConfigReloader::new(app.live_settings.clone(), test_configuration());
+    let mut reloaded = test_configuration();
+    reloaded.api.token = Secret::new("rotated-token".to_string());
+    // Structural: ignored, the app keeps listening where it is
+    reloaded.server.port += 1;
+
+    // ACT
+    let changes = reloader.apply(Ok(reloaded)).unwrap();
+
+    // ASSERT
+    assert_eq!(changes, vec!["api.token: changed".to_string()]);
+    let client = &app.api_client;
+    let url = format!("{}/api/v1/subscribers", app.root_address);
+    for (token, expected_status) in [(app.api_token.as_str(), 401), ("rotated-token", 200)] {
+        let response = client.get(&url).bearer_auth(token).send().await.unwrap();
+        assert_eq!(expected_status, response.status().as_u16());
+    }
+}
+
+#[tokio::test]
+async fn an_invalid_configuration_is_not_applied() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let reloader = ConfigReloader::new(app.live_settings.clone(), test_configuration());
+
+    // ACT
+    let result = reloader.apply(Err(ConfigurationError::Invalid(vec![])));
+
+    // ASSERT
+    assert!(result.is_err());
+    let response = app
+        .api_request(reqwest::Method::GET, "/subscribers")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[test]
+fn editing_a_watched_file_reloads_the_configuration() {
+    // ARRANGE
+    let dir = config_dir(VALID_BASE_YAML);
+    let load = {
+        let dir = dir.clone();
+        move || load_configuration(&dir, &Environment::Local, vec![])
+    };
+    let settings = load().unwrap();
+    let live = Arc::new(LiveSettings::new(&settings));
+    let _watcher = ConfigReloader::new(live.clone(), settings)
+        .watch(&dir, load)
+        .expect("Failed to watch the configuration directory");
+
+    // ACT
+    std::fs::write(
+        dir.join("base.yaml"),
+        VALID_BASE_YAML.replace("token: a-token", "token: edited-token"),
+    )
+    .unwrap();
+
+    // ASSERT
+    // The reload happens on the watcher thread: poll until it shows up (or give up)
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while live.current().api.token.expose_secret() != "edited-token" {
+        assert!(Instant::now() < deadline, "The edit was not picked up");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
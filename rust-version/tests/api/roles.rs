@@ -0,0 +1,194 @@
+//! tests/api/roles.rs
+//! Admins calling `/api/v1` with their password, within the permissions of their role.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use reqwest::Method;
+use zero2prod::authentication::Role;
+
+use crate::helpers::{Caller, json_body, spawn_app};
+
+#[tokio::test]
+async fn each_role_is_granted_its_own_routes_only() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let analyst = app.login("ana", Role::Analyst).await;
+    let editor = app.login("ed", Role::Editor).await;
+    let owner = app.login("olga", Role::Owner).await;
+    let new_subscriber = serde_json::json!({"email": "ursula@example.com", "name": "le guin"});
+    let test_cases = vec![
+        (&analyst, Method::GET, "/subscribers", 200),
+        (&analyst, Method::GET, "/metrics", 200),
+        (&analyst, Method::POST, "/subscribers", 403),
+        (&editor, Method::POST, "/subscribers", 201),
+        (&editor, Method::GET, "/email-domain-rules", 200),
+        (&editor, Method::GET, "/api-keys", 403),
+        (&editor, Method::GET, "/users", 403),
+        (&owner, Method::GET, "/api-keys", 200),
+        (&owner, Method::GET, "/users", 200),
+    ];
+
+    for (caller, method, path, status) in test_cases {
+        // ACT
+        let response = app
+            .api_request_as(caller, method.clone(), path)
+            .header("Content-Type", "application/json")
+            .body(new_subscriber.to_string())
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // ASSERT
+        assert_eq!(
+            response.status().as_u16(),
+            status,
+            "{:?} {} {}",
+            caller,
+            method,
+            path
+        );
+    }
+}
+
+#[tokio::test]
+async fn forbidden_access_is_recorded_in_the_audit_log() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let analyst = app.login("ana", Role::Analyst).await;
+
+    // ACT
+    let response = app
+        .api_request_as(
+            &analyst,
+            Method::DELETE,
+            "/subscribers/00000000-0000-0000-0000-000000000000",
+        )
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(json_body(response).await["code"], "insufficient_scope");
+    let audit = sqlx::query!("SELECT actor, action, target, reason FROM audit_log")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .expect("The denied access was not recorded");
+    assert_eq!(audit.actor, "user:ana");
+    assert_eq!(audit.action, "access.denied");
+    assert_eq!(
+        audit.target.as_deref(),
+        Some("DELETE /api/v1/subscribers/00000000-0000-0000-0000-000000000000")
+    );
+    assert_eq!(
+        audit.reason.as_deref(),
+        Some("Missing the `subscribers:write` scope")
+    );
+}
+
+#[tokio::test]
+async fn a_wrong_password_or_an_unknown_user_gets_a_401() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let Caller::Admin { password, .. } = app.login("olga", Role::Owner).await else {
+        unreachable!()
+    };
+    let test_cases = [("olga", "not-the-password"), ("nobody", password.as_str())];
+
+    for (username, password) in test_cases {
+        let caller = Caller::Admin {
+            username: username.to_string(),
+            password: password.to_string(),
+        };
+
+        // ACT
+        let response = app
+            .api_request_as(&caller, Method::GET, "/metrics")
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // ASSERT
+        assert_eq!(response.status().as_u16(), 401, "{}", username);
+        assert_eq!(
+            response.headers()["WWW-Authenticate"],
+            r#"Bearer, Basic realm="zero2prod""#
+        );
+    }
+}
+
+#[tokio::test]
+async fn owners_change_roles_but_never_demote_the_last_owner() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let owner = app.login("olga", Role::Owner).await;
+    let analyst = app.login("ana", Role::Analyst).await;
+    let set_role = |username: &str, role: &str| {
+        app.api_request_as(&owner, Method::PUT, &format!("/users/{}/role", username))
+            .header("Content-Type", "application/json")
+            .body(serde_json::json!({ "role": role }).to_string())
+            .send()
+    };
+
+    // ACT
+    let demoted = set_role("olga", "editor").await.unwrap();
+    let promoted = set_role("ana", "editor").await.unwrap();
+    let unknown = set_role("nobody", "editor").await.unwrap();
+
+    // ASSERT
+    assert_eq!(demoted.status().as_u16(), 409);
+    assert_eq!(json_body(demoted).await["code"], "last_owner");
+    assert_eq!(promoted.status().as_u16(), 200);
+    assert_eq!(json_body(promoted).await["role"], "editor");
+    assert_eq!(unknown.status().as_u16(), 404);
+    assert_eq!(json_body(unknown).await["code"], "user_not_found");
+    // Effective right away
+    let response = app
+        .api_request_as(&analyst, Method::POST, "/subscribers")
+        .header("Content-Type", "application/json")
+        .body(serde_json::json!({"email": "ursula@example.com", "name": "le guin"}).to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+    let audit = sqlx::query!(
+        "SELECT actor, target, reason FROM audit_log WHERE action = 'user.role_changed'"
+    )
+    .fetch_one(&app.db_conn_pool)
+    .await
+    .unwrap();
+    assert_eq!(audit.actor, "user:olga");
+    assert_eq!(audit.target.as_deref(), Some("user:ana"));
+    assert_eq!(audit.reason.as_deref(), Some("analyst -> editor"));
+}
+
+#[tokio::test]
+async fn the_authentication_scheme_is_case_insensitive() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let Caller::Admin { password, .. } = app.login("olga", Role::Owner).await else {
+        unreachable!()
+    };
+    let basic = BASE64.encode(format!("olga:{}", password));
+    let test_cases = vec![
+        format!("bearer {}", app.api_token),
+        format!("BEARER {}", app.api_token),
+        format!("basic {}", basic),
+        format!("BaSiC {}", basic),
+    ];
+
+    for authorization in test_cases {
+        // ACT
+        // By hand, unlike everywhere else: the spelling of the header is what is tested
+        let response = app
+            .api_client
+            .get(format!("{}/api/v1/metrics", app.root_address))
+            .header("Authorization", &authorization)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // ASSERT
+        assert_eq!(response.status().as_u16(), 200, "{}", authorization);
+    }
+}
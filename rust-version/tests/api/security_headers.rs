@@ -0,0 +1,44 @@
+//! tests/api/security_headers.rs
+//! Browser hardening headers on every response.
+
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn every_response_carries_the_security_headers() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("/health_check", 200),
+        ("/openapi.json", 200),
+        // Problems included
+        ("/no-such-page", 404),
+    ];
+
+    for (path, status) in test_cases {
+        // ACT
+        let response = app
+            .api_client
+            .get(format!("{}{}", app.root_address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // ASSERT
+        assert_eq!(response.status().as_u16(), status, "{}", path);
+        let headers = response.headers();
+        assert_eq!(headers["X-Content-Type-Options"], "nosniff", "{}", path);
+        assert_eq!(
+            headers["Referrer-Policy"], "strict-origin-when-cross-origin",
+            "{}",
+            path
+        );
+        assert_eq!(headers["X-Frame-Options"], "DENY", "{}", path);
+        // No HTML, no CSP. Plain HTTP, no HSTS (see `tls`)
+        assert!(!headers.contains_key("Content-Security-Policy"), "{}", path);
+        assert!(
+            !headers.contains_key("Strict-Transport-Security"),
+            "{}",
+            path
+        );
+    }
+}
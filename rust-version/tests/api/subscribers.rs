@@ -0,0 +1,190 @@
+//! tests/api/subscribers.rs
+//! The `/api/v1/subscribers` management API.
+
+use crate::helpers::{json_body, spawn_app};
+
+#[tokio::test]
+async fn api_rejects_requests_without_a_valid_bearer_token() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let client = &app.api_client;
+    let test_cases = vec![
+        (None, "no token"),
+        (Some("not-the-right-token"), "a wrong token"),
+    ];
+
+    for (token, description) in test_cases {
+        let mut request = client.get(format!("{}/api/v1/subscribers", app.root_address));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+
+        // ACT
+        let response = request.send().await.expect("Failed to execute request.");
+
+        // ASSERT
+        assert_eq!(401, response.status().as_u16(), "with {}", description);
+        assert_eq!(json_body(response).await["code"], "unauthorized");
+    }
+}
+
+#[tokio::test]
+async fn api_creates_then_gets_a_pending_subscriber() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let created = app
+        .create_subscriber(serde_json::json!({
+            "email": "ursula_le_guin@gmail.com",
+            "name": "le guin"
+        }))
+        .await;
+    assert_eq!(201, created.status().as_u16());
+    let created = json_body(created).await;
+    let fetched = app
+        .api_request(
+            reqwest::Method::GET,
+            &format!("/subscribers/{}", created["id"].as_str().unwrap()),
+        )
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    assert_eq!(200, fetched.status().as_u16());
+    let fetched = json_body(fetched).await;
+    assert_eq!(fetched, created);
+    assert_eq!(fetched["status"], "pending_confirmation");
+}
+
+#[tokio::test]
+async fn api_bypasses_double_opt_in_only_with_an_audit_reason() {
+    // ARRANGE
+    let app = spawn_app().await;
+
+    // ACT
+    let without_reason = app
+        .create_subscriber(serde_json::json!({
+            "email": "ursula_le_guin@gmail.com",
+            "name": "le guin",
+            "already_consented": true
+        }))
+        .await;
+    let with_reason = app
+        .create_subscriber(serde_json::json!({
+            "email": "ursula_le_guin@gmail.com",
+            "name": "le guin",
+            "already_consented": true,
+            "audit_reason": "Signed up at the 2025 book fair"
+        }))
+        .await;
+
+    // ASSERT
+    assert_eq!(422, without_reason.status().as_u16());
+    assert_eq!(
+        json_body(without_reason).await["code"],
+        "audit_reason_required"
+    );
+    assert_eq!(201, with_reason.status().as_u16());
+    assert_eq!(json_body(with_reason).await["status"], "confirmed");
+    let audit = sqlx::query!("SELECT action, reason FROM audit_log")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .expect("Failed to fetch the audit log");
+    assert_eq!(audit.action, "subscriber.created_without_opt_in");
+    assert_eq!(
+        audit.reason.as_deref(),
+        Some("Signed up at the 2025 book fair")
+    );
+}
+
+#[tokio::test]
+async fn api_lists_subscribers_with_filters_and_cursor_pagination() {
+    // ARRANGE
+    let app = spawn_app().await;
+    for i in 0..5 {
+        let response = app
+            .create_subscriber(serde_json::json!({
+                "email": format!("reader_{}@gmail.com", i),
+                "name": format!("reader {}", i)
+            }))
+            .await;
+        assert_eq!(201, response.status().as_u16());
+    }
+    app.create_subscriber(serde_json::json!({
+        "email": "ursula_le_guin@gmail.com",
+        "name": "le guin"
+    }))
+    .await;
+
+    // ACT
+    let mut emails = vec![];
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut path = "/subscribers?limit=2&email_prefix=reader_".to_string();
+        if let Some(cursor) = &cursor {
+            path.push_str(&format!("&cursor={}", cursor));
+        }
+        let response = app
+            .api_request(reqwest::Method::GET, &path)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_eq!(200, response.status().as_u16());
+        let page = json_body(response).await;
+        for subscriber in page["data"].as_array().unwrap() {
+            emails.push(subscriber["email"].as_str().unwrap().to_string());
+        }
+        match page["next_cursor"].as_str() {
+            Some(next) => cursor = Some(next.to_string()),
+            None => break,
+        }
+    }
+
+    // ASSERT
+    let expected: Vec<String> = (0..5).map(|i| format!("reader_{}@gmail.com", i)).collect();
+    assert_eq!(emails, expected);
+}
+
+#[tokio::test]
+async fn api_updates_and_deletes_a_subscriber() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let created = app
+        .create_subscriber(serde_json::json!({
+            "email": "ursula_le_guin@gmail.com",
+            "name": "le guin"
+        }))
+        .await;
+    let path = format!(
+        "/subscribers/{}",
+        json_body(created).await["id"].as_str().unwrap()
+    );
+
+    // ACT
+    let updated = app
+        .api_request(reqwest::Method::PATCH, &path)
+        .header("Content-Type", "application/json")
+        .body(r#"{"name": "Ursula K. Le Guin"}"#)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let deleted = app
+        .api_request(reqwest::Method::DELETE, &path)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let fetched = app
+        .api_request(reqwest::Method::GET, &path)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    assert_eq!(200, updated.status().as_u16());
+    assert_eq!(json_body(updated).await["name"], "Ursula K. Le Guin");
+    assert_eq!(204, deleted.status().as_u16());
+    assert_eq!(404, fetched.status().as_u16());
+    assert_eq!(json_body(fetched).await["code"], "subscriber_not_found");
+}
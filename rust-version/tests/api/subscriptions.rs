@@ -0,0 +1,224 @@
+//! tests/api/subscriptions.rs
+//! The public `POST /subscription` form (the form-encoded happy/sad paths live in `tests/conformance`).
+
+use crate::helpers::{json_body, spawn_app};
+
+#[tokio::test]
+async fn subscribe_persists_the_new_subscriber_as_pending_confirmation() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com".to_string();
+
+    // ACT
+    let response = app.post_subscriptions(body).await;
+
+    // ASSERT
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions;")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribe_returns_200_ok_for_valid_json_data() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let body = r#"{"name": "le guin", "email": "ursula_le_guin@gmail.com"}"#.to_string();
+
+    // ACT
+    let response = app.post_subscriptions_as(body, "application/json").await;
+
+    let saved = sqlx::query!("SELECT email, name FROM subscriptions;")
+        .fetch_one(&app.db_conn_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+
+    // ASSERT
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name, "le guin");
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_json_400_naming_the_missing_field() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (r#"{"name": "le guin"}"#, "email"),
+        (r#"{"email": "ursula_le_guin@gmail.com"}"#, "name"),
+        (
+            r#"{"name": 42, "email": "ursula_le_guin@gmail.com"}"#,
+            "name",
+        ),
+    ];
+
+    for (invalid_body, missing_field) in test_cases {
+        // ACT
+        let response = app
+            .post_subscriptions_as(invalid_body.to_string(), "application/json")
+            .await;
+
+        // ASSERT
+        assert_eq!(400, response.status().as_u16());
+        assert_eq!(
+            json_body(response).await["field"],
+            missing_field,
+            "The error did not point at the `{}` field for payload {}.",
+            missing_field,
+            invalid_body
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribe_returns_415_for_unsupported_content_types() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com".to_string();
+
+    // ACT
+    let response = app.post_subscriptions_as(body, "text/plain").await;
+
+    // ASSERT
+    assert_eq!(415, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_accepts_media_types_whatever_their_case_or_parameters() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("Application/JSON", "json"),
+        ("application/json; charset=UTF-8", "json"),
+        ("application/merge-patch+json", "json"),
+        ("APPLICATION/X-WWW-FORM-URLENCODED", "form"),
+    ];
+
+    for (i, (content_type, format)) in test_cases.into_iter().enumerate() {
+        let email = format!("ursula{}@gmail.com", i);
+        let body = match format {
+            "json" => serde_json::json!({"name": "le guin", "email": email}).to_string(),
+            _ => format!("name=le%20guin&email={}", email.replace('@', "%40")),
+        };
+
+        // ACT
+        let response = app.post_subscriptions_as(body, content_type).await;
+
+        // ASSERT
+        assert_eq!(200, response.status().as_u16(), "{}", content_type);
+    }
+}
+
+#[tokio::test]
+async fn subscribe_stores_the_canonical_form_of_the_email() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            " Ursula@GMAIL.com ",
+            "Ursula@gmail.com",
+            "trimmed, domain lowercased",
+        ),
+        (
+            "ursula@Bücher.example",
+            "ursula@xn--bcher-kva.example",
+            "punycoded domain",
+        ),
+    ];
+
+    for (email, canonical, description) in test_cases {
+        let body = serde_json::json!({"name": "le guin", "email": email});
+
+        // ACT
+        let response = app
+            .post_subscriptions_as(body.to_string(), "application/json")
+            .await;
+
+        // ASSERT
+        assert_eq!(200, response.status().as_u16(), "{}", description);
+        let saved: String =
+            sqlx::query_scalar("SELECT email FROM subscriptions WHERE lower(email) = lower($1)")
+                .bind(canonical)
+                .fetch_one(&app.db_conn_pool)
+                .await
+                .expect("Failed to fetch saved subscription");
+        assert_eq!(saved, canonical, "{}", description);
+    }
+}
+
+#[tokio::test]
+async fn an_email_differing_only_by_case_is_the_same_subscriber() {
+    // ARRANGE
+    let app = spawn_app().await;
+    app.create_subscriber(serde_json::json!({"email": "Ursula@Gmail.com", "name": "le guin"}))
+        .await;
+
+    // ACT
+    let response = app
+        .create_subscriber(serde_json::json!({"email": "ursula@gmail.com", "name": "le guin"}))
+        .await;
+
+    // ASSERT
+    assert_eq!(409, response.status().as_u16());
+    assert_eq!(
+        json_body(response).await["code"],
+        "email_already_subscribed"
+    );
+}
+
+#[tokio::test]
+async fn subscribing_again_whatever_the_case_is_a_no_op() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let first = app
+        .post_subscriptions("name=first&email=Ursula%40Gmail.com".to_string())
+        .await;
+    assert_eq!(200, first.status().as_u16());
+
+    // ACT
+    let response = app
+        .post_subscriptions("name=second&email=%20ursula%40gmail.com".to_string())
+        .await;
+
+    // ASSERT
+    // Same answer as a new subscription: the form does not tell who is subscribed
+    assert_eq!(200, response.status().as_u16());
+    let saved: Vec<String> = sqlx::query_scalar!("SELECT name FROM subscriptions")
+        .fetch_all(&app.db_conn_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved, vec!["first".to_string()]);
+}
+
+#[tokio::test]
+async fn subscribe_returns_422_for_an_invalid_email() {
+    // ARRANGE
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("ursula_le_guin", "no @"),
+        ("@gmail.com", "no local part"),
+        ("ursula@localhost", "no top-level domain"),
+        ("ursula le guin@gmail.com", "a space"),
+    ];
+
+    for (email, description) in test_cases {
+        let body = serde_json::json!({"name": "le guin", "email": email});
+
+        // ACT
+        let response = app
+            .post_subscriptions_as(body.to_string(), "application/json")
+            .await;
+
+        // ASSERT
+        assert_eq!(422, response.status().as_u16(), "{}", description);
+        assert_eq!(
+            json_body(response).await["code"],
+            "invalid_email",
+            "{}",
+            description
+        );
+    }
+}
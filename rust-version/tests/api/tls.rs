@@ -0,0 +1,126 @@
+//! tests/api/tls.rs
+//! Native HTTPS: self-signed certificates generated on the fly, reloaded on change.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+use zero2prod::configuration::TlsSettings;
+
+use crate::helpers::{TestApp, spawn_app_with};
+
+// A throwaway self-signed certificate for `127.0.0.1`, returned as PEM
+fn self_signed_certificate() -> (String, String) {
+    let rcgen::CertifiedKey { cert, key_pair } =
+        rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+    (cert.pem(), key_pair.serialize_pem())
+}
+
+fn write_certificate(dir: &Path, (cert, key): &(String, String)) {
+    std::fs::write(dir.join("cert.pem"), cert).unwrap();
+    std::fs::write(dir.join("key.pem"), key).unwrap();
+}
+
+// The app, over HTTPS with `certificate` (written in a throwaway directory)
+async fn spawn_https_app(certificate: &(String, String)) -> (TestApp, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("zero2prod-tls-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    write_certificate(&dir, certificate);
+    let tls = TlsSettings {
+        cert_path: dir.join("cert.pem"),
+        key_path: dir.join("key.pem"),
+        redirect_port: Some(0),
+    };
+    let app = spawn_app_with(|config| {
+        // TCP, whatever `TEST_TRANSPORT` says: Unix sockets are plain HTTP
+        config.server.host = vec!["127.0.0.1".to_string()];
+        config.server.tls = Some(tls);
+    })
+    .await;
+    (app, dir)
+}
+
+// A client trusting `certificate` only
+fn client_trusting((cert, _): &(String, String)) -> reqwest::Client {
+    reqwest::Client::builder()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(reqwest::Certificate::from_pem(cert.as_bytes()).unwrap())
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn https_is_served_and_plain_http_is_redirected_to_it() {
+    // ARRANGE
+    let certificate = self_signed_certificate();
+    let (app, dir) = spawn_https_app(&certificate).await;
+    let client = client_trusting(&certificate);
+    let redirect_address = app.redirect_address.clone().unwrap();
+
+    // ACT
+    let https_response = client
+        .get(format!("{}/health_check", app.root_address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let http_response = client
+        .post(format!("{}/subscription?source=home", redirect_address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    assert!(app.root_address.starts_with("https://127.0.0.1:"));
+    assert_eq!(https_response.status().as_u16(), 200);
+    assert_eq!(
+        https_response.headers()["Strict-Transport-Security"],
+        "max-age=31536000"
+    );
+    // 308: the client must repeat the POST (not turn it into a GET)
+    assert_eq!(http_response.status().as_u16(), 308);
+    assert_eq!(
+        http_response.headers()["Location"],
+        format!("{}/subscription?source=home", app.root_address).as_str()
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn a_renewed_certificate_is_served_without_dropping_connections() {
+    // ARRANGE
+    let old_certificate = self_signed_certificate();
+    let new_certificate = self_signed_certificate();
+    let (app, dir) = spawn_https_app(&old_certificate).await;
+    let health_check = format!("{}/health_check", app.root_address);
+    // Its connection is kept alive (and reused) by the client pool
+    let old_client = client_trusting(&old_certificate);
+    let response = old_client.get(&health_check).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // ACT
+    write_certificate(&dir, &new_certificate);
+
+    // ASSERT
+    // New connections get the new certificate, once the change is picked up
+    let new_client = client_trusting(&new_certificate);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while new_client.get(&health_check).send().await.is_err() {
+        assert!(
+            Instant::now() < deadline,
+            "The new certificate was not picked up"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    // The established connection still works: a new handshake would have failed,
+    // `old_client` does not trust the new certificate
+    let response = old_client.get(&health_check).send().await;
+    assert_eq!(
+        response
+            .expect("The connection was dropped")
+            .status()
+            .as_u16(),
+        200
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,135 @@
+//! tests/conformance/main.rs
+//!
+//! HTTP-level scenarios that EVERY implementation of the service must pass,
+//! whatever it is written in (`rust-version` or `scala-version`).
+//!
+//! - By default, each scenario runs against the Rust app, spawned in-process:
+//!   `cargo test --test conformance`
+//! - To target an already running backend (e.g. the Scala twin):
+//!   `CONFORMANCE_BASE_URL=http://127.0.0.1:8001 cargo test --test conformance`
+//!   add `CONFORMANCE_DATABASE_URL=postgres://...` to also verify what was persisted.
+//!
+//! Scenarios must only rely on the public HTTP contract (and the shared database schema):
+//! Rust-specific behaviour belongs to `tests/api`.
+
+mod target;
+
+use target::Target;
+use uuid::Uuid;
+
+// `tokio::test` is the testing equivalent of `tokio::main`.
+// It also spares you from having to specify the `#[test]` attribute. //
+// You can inspect what code gets generated using
+// `cargo expand --test conformance` (<- name of the test binary)
+#[tokio::test]
+async fn health_check_works() {
+    // ARRANGE
+    let target = Target::from_env().await;
+    // use REQWEST to perform HTTP requests against our app
+    let client = reqwest::Client::new();
+
+    // ACT
+    let response = client
+        .get(target.url("/health_check"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // ASSERT
+    assert!(response.status().is_success());
+    assert_eq!(Some(0), response.content_length());
+
+    // A NOTE ON CLEAN-UP / TEARDOWN
+    // when a tokio runtime is shut down all tasks spawned on it are dropped.
+    // tokio::test spins up a new runtime at the beginning of each test case and they shut down at the end of each test case.
+}
+
+#[tokio::test]
+async fn subscribe_returns_200_ok_for_valid_form_data() {
+    // ARRANGE
+    let target = Target::from_env().await;
+    let client = reqwest::Client::new();
+    // A backend under test may share its database across scenarios (and runs):
+    // a unique email keeps this scenario independent of the others.
+    let email = format!("ursula_le_guin_{}@gmail.com", Uuid::new_v4());
+
+    // ACT
+    let response = client
+        .post(target.url("/subscription"))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(format!(
+            "name=le%20guin&email={}",
+            email.replace('@', "%40")
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // ASSERT
+    assert_eq!(200, response.status().as_u16());
+
+    let Some(db_conn_pool) = &target.db_conn_pool else {
+        return;
+    };
+    let saved = sqlx::query!(
+        "SELECT email, name FROM subscriptions WHERE lower(email) = lower($1);",
+        email
+    )
+    /*
+     * What is the type of saved?
+     * The query! macro returns an anonymous record type:
+     * a struct definition is generated at compile-time after having verified that the query is valid,
+     * with a member for each column on the result (i.e. saved.email for the email column)
+     */
+    .fetch_one(db_conn_pool)
+    .await
+    .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, email);
+    assert_eq!(saved.name, "le guin");
+}
+
+#[tokio::test]
+async fn subscribe_returns_400_when_data_is_missing() {
+    // ARRANGE
+    let target = Target::from_env().await;
+    let client = reqwest::Client::new();
+
+    // NOTE: the 400 Bad Request responses come from the body extractor, not from the handler:
+    // when FormData cannot be deserialized from the request body (missing required fields),
+    // the extraction fails BEFORE the handler runs.
+    //
+    // SCALA EQUIVALENT (http4s):
+    //   case req @ POST -> Root / "subscription" =>
+    //     req.as[FormData].flatMap { form => Ok() }
+    //
+    // If req.as[FormData] fails (missing fields, invalid format), http4s automatically
+    // returns 400 Bad Request via DecodeFailure → MalformedMessageBodyFailure handling.
+    // The Ok() block never runs, just like our Rust handler never runs on extraction failure.
+    //
+    // Both frameworks use the same pattern: typeclass-based decoding with automatic error handling.
+    let test_cases = vec![
+        ("name=le%20guin", "missing the email"),
+        ("email=ursula_le_guin%40gmail.com", "missing the name"),
+        ("", "missing both name and email"),
+    ];
+
+    for (invalid_body, error_msg) in test_cases {
+        // ACT
+        let response = client
+            .post(target.url("/subscription"))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(invalid_body)
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // ASSERT
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            // Additional customised error message on test failure
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            error_msg
+        )
+    }
+}
@@ -0,0 +1,60 @@
+//! tests/conformance/target.rs
+//! The backend under test: an already running one (any implementation), or our own, in-process.
+
+use sqlx::PgPool;
+
+// The in-process app comes from the `tests/api` harness (fresh database, dropped on teardown).
+// Not every helper is needed here, hence the `allow`.
+#[allow(dead_code)]
+#[path = "../api/helpers.rs"]
+mod helpers;
+
+use helpers::{TestApp, spawn_app};
+
+/// Base URL of the backend to test, e.g. `http://127.0.0.1:8001` for the Scala twin.
+/// When unset, the Rust app is spawned in-process.
+pub const BASE_URL_VAR: &str = "CONFORMANCE_BASE_URL";
+/// Connection string of the database used by that backend.
+/// Optional: scenarios skip their database assertions without it.
+pub const DATABASE_URL_VAR: &str = "CONFORMANCE_DATABASE_URL";
+
+pub struct Target {
+    // nota: without a trailing slash, e.g. `http://127.0.0.1:8000`
+    pub base_url: String,
+    pub db_conn_pool: Option<PgPool>,
+    // Keeps the in-process app (and its database) alive until the scenario is over
+    _app: Option<TestApp>,
+}
+
+impl Target {
+    /// Resolve the target from the environment (see `BASE_URL_VAR`, `DATABASE_URL_VAR`).
+    pub async fn from_env() -> Self {
+        match std::env::var(BASE_URL_VAR) {
+            Ok(base_url) => {
+                let db_conn_pool = std::env::var(DATABASE_URL_VAR).ok().map(|url| {
+                    PgPool::connect_lazy(&url)
+                        .unwrap_or_else(|_| panic!("Invalid {}: {}", DATABASE_URL_VAR, url))
+                });
+                Self {
+                    base_url: base_url.trim_end_matches('/').to_string(),
+                    db_conn_pool,
+                    _app: None,
+                }
+            }
+            Err(_) => Self::spawn_rust_app().await,
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    async fn spawn_rust_app() -> Self {
+        let app = spawn_app().await;
+        Self {
+            base_url: app.root_address.clone(),
+            db_conn_pool: Some(app.db_conn_pool.clone()),
+            _app: Some(app),
+        }
+    }
+}
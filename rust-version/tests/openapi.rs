@@ -0,0 +1,34 @@
+//! tests/openapi.rs
+
+use std::path::Path;
+
+use utoipa::OpenApi;
+use zero2prod::openapi::ApiDoc;
+
+// The frontend and the Scala twin code against the COMMITTED `openapi.json`:
+// any change to the handlers' annotations or to the request/response types
+// must be reflected in it (and reviewed) in the same commit.
+//
+// To regenerate it: `UPDATE_OPENAPI=true cargo test --test openapi`
+#[test]
+fn committed_openapi_spec_matches_the_generated_one() {
+    // ARRANGE
+    let spec_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("openapi.json");
+    let generated = ApiDoc::openapi()
+        .to_pretty_json()
+        .expect("Failed to serialize the OpenAPI document")
+        + "\n";
+    if std::env::var("UPDATE_OPENAPI").is_ok() {
+        std::fs::write(&spec_path, &generated).expect("Failed to write openapi.json");
+    }
+
+    // ACT
+    let committed = std::fs::read_to_string(&spec_path).unwrap_or_default();
+
+    // ASSERT
+    // NOTE: not `assert_eq!`: dumping two multi-thousand-line documents helps no one.
+    assert!(
+        committed == generated,
+        "`openapi.json` is out of date: run `UPDATE_OPENAPI=true cargo test --test openapi` and commit the result."
+    );
+}